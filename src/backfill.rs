@@ -0,0 +1,464 @@
+use std::collections::{BTreeMap, HashMap};
+
+use influxdb2::models::Query;
+use influxdb2_derive::WriteDataPoint;
+use influxdb2_structmap::value::Value;
+use time::{Duration, OffsetDateTime};
+
+use fronius_api::fronius::{ArchiveChannel, ArchiveData, ArchiveScope, DeviceId, DeviceType, Fronius};
+use crate::{
+    get_device_tags, get_inverter_tags,
+    influx::{self, flux_string, flux_tag_filters, InfluxSink, TagValue},
+    inventory::Inventory,
+    DeviceTags, InverterTags,
+};
+
+#[derive(Default, Debug, WriteDataPoint)]
+#[measurement = "inverter"]
+struct BackfillInverterData {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(tag)]
+    custom_name: TagValue,
+    #[influxdb(tag)]
+    backfilled: String,
+    #[influxdb(field)]
+    ac_power: Option<f64>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+#[derive(Default, Debug, WriteDataPoint)]
+#[measurement = "inverter_mppt"]
+struct BackfillInverterMpptData {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(tag)]
+    custom_name: TagValue,
+    #[influxdb(tag)]
+    mppt: String,
+    #[influxdb(tag)]
+    backfilled: String,
+    #[influxdb(field)]
+    dc_current: Option<f64>,
+    #[influxdb(field)]
+    dc_voltage: Option<f64>,
+    #[influxdb(field)]
+    dc_power: Option<f64>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+#[derive(Default, Debug, WriteDataPoint)]
+#[measurement = "meter"]
+struct BackfillMeterData {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(tag)]
+    backfilled: String,
+    #[influxdb(field)]
+    l1_current: Option<f64>,
+    #[influxdb(field)]
+    l2_current: Option<f64>,
+    #[influxdb(field)]
+    l3_current: Option<f64>,
+    #[influxdb(field)]
+    l1_voltage: Option<f64>,
+    #[influxdb(field)]
+    l2_voltage: Option<f64>,
+    #[influxdb(field)]
+    l3_voltage: Option<f64>,
+    #[influxdb(field)]
+    power: Option<f64>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+#[derive(Default, Debug, WriteDataPoint)]
+#[measurement = "storage"]
+struct BackfillStorageData {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(tag)]
+    backfilled: String,
+    #[influxdb(field)]
+    charge_percentage: Option<f64>,
+    #[influxdb(field)]
+    dc_current: Option<f64>,
+    #[influxdb(field)]
+    dc_voltage: Option<f64>,
+    #[influxdb(field)]
+    temperature_cell: Option<f64>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+/// Returns the newest timestamp written to `measurement` with the given tags within the
+/// last `max_days` days.
+fn last_written(influx: &InfluxSink, measurement: &str, tags: &[(String, String)], max_days: i64) -> Result<Option<OffsetDateTime>, Box<dyn std::error::Error>> {
+    let tag_filters = flux_tag_filters(tags);
+    let query = Query::new(format!(
+        r#"from(bucket: {})
+            |> range(start: -{max_days}d)
+            |> filter(fn: (r) => r._measurement == {}){tag_filters}
+            |> keep(columns: ["_time"])
+            |> group()
+            |> sort(columns: ["_time"], desc: true)
+            |> limit(n: 1)"#,
+        flux_string(influx.bucket()),
+        flux_string(measurement),
+    ));
+    let records = influx.runtime().block_on(influx.client().query_raw(Some(query)))?;
+
+    let last = records.into_iter().find_map(|record| match record.values.get("_time") {
+        Some(Value::TimeRFC(time)) => OffsetDateTime::from_unix_timestamp_nanos(time.timestamp_nanos_opt()? as i128).ok(),
+        _ => None,
+    });
+    Ok(last)
+}
+
+type ArchiveRows = BTreeMap<OffsetDateTime, HashMap<ArchiveChannel, f64>>;
+
+/// Reads the given channels from the archive in chunks of one day and merges all
+/// series into one row per timestamp, ordered by time.
+fn read_archive(fronius: &Fronius, scope: &ArchiveScope, channels: &[ArchiveChannel], start: OffsetDateTime, end: OffsetDateTime) -> Result<ArchiveRows, Box<dyn std::error::Error>> {
+    let mut rows = ArchiveRows::new();
+    let mut chunk_start = start;
+    while chunk_start < end {
+        let chunk_end = std::cmp::min(chunk_start + Duration::days(1), end);
+        let data: ArchiveData = fronius.get_archive_data(chunk_start, chunk_end, channels, scope)?;
+        merge_archive(&mut rows, &data, channels, start, end);
+        chunk_start = chunk_end;
+    }
+    Ok(rows)
+}
+
+/// Adds the values of `channels` within `(start, end]` to `rows`.
+fn merge_archive(rows: &mut ArchiveRows, data: &ArchiveData, channels: &[ArchiveChannel], start: OffsetDateTime, end: OffsetDateTime) {
+    for device in data.values() {
+        for channel in channels {
+            let Some(series) = device.channel(*channel) else {
+                continue;
+            };
+            for (time, value) in &series.values {
+                if *time > start && *time <= end {
+                    rows.entry(*time).or_default().insert(*channel, *value);
+                }
+            }
+        }
+    }
+}
+
+fn timestamp(time: &OffsetDateTime) -> i64 {
+    time.unix_timestamp_nanos() as i64
+}
+
+/// The DC channels of the archive per MPP tracker.
+const MPPT_CHANNELS: [(u8, ArchiveChannel, ArchiveChannel); 2] = [
+    (1, ArchiveChannel::CurrentDcString1, ArchiveChannel::VoltageDcString1),
+    (2, ArchiveChannel::CurrentDcString2, ArchiveChannel::VoltageDcString2),
+];
+
+type InverterPoints = (Vec<BackfillInverterData>, Vec<BackfillInverterMpptData>);
+
+/// Returns the `inverter` points and, as the archive only has the DC values per string,
+/// the `inverter_mppt` points of an inverter.
+fn backfill_inverter(fronius: &Fronius, device_id: &DeviceId, tags: &InverterTags, start: OffsetDateTime, end: OffsetDateTime) -> Result<InverterPoints, Box<dyn std::error::Error>> {
+    let scope = ArchiveScope::Device(DeviceType::Inverter, *device_id);
+    let rows = read_archive(fronius, &scope, &[
+        ArchiveChannel::PowerRealPacSum,
+        ArchiveChannel::CurrentDcString1,
+        ArchiveChannel::VoltageDcString1,
+        ArchiveChannel::CurrentDcString2,
+        ArchiveChannel::VoltageDcString2,
+    ], start, end)?;
+
+    let data = rows.iter()
+        .filter(|(_, row)| row.contains_key(&ArchiveChannel::PowerRealPacSum))
+        .map(|(time, row)| BackfillInverterData {
+            device: "Inverter".to_owned(),
+            device_id: tags.device_id.to_owned(),
+            serial: tags.serial.clone(),
+            custom_name: tags.custom_name.clone(),
+            backfilled: "true".to_owned(),
+            ac_power: row.get(&ArchiveChannel::PowerRealPacSum).copied(),
+            time: timestamp(time),
+        })
+        .collect();
+    let mppt_data = rows.iter()
+        .flat_map(|(time, row)| MPPT_CHANNELS.iter().map(move |(mppt, current, voltage)| (time, mppt, row.get(current).copied(), row.get(voltage).copied())))
+        .filter(|(_, _, current, voltage)| current.is_some() || voltage.is_some())
+        .map(|(time, mppt, current, voltage)| BackfillInverterMpptData {
+            device: "Inverter".to_owned(),
+            device_id: tags.device_id.to_owned(),
+            serial: tags.serial.clone(),
+            custom_name: tags.custom_name.clone(),
+            mppt: mppt.to_string(),
+            backfilled: "true".to_owned(),
+            dc_current: current,
+            dc_voltage: voltage,
+            dc_power: current.zip(voltage).map(|(current, voltage)| current * voltage),
+            time: timestamp(time),
+        })
+        .collect();
+    Ok((data, mppt_data))
+}
+
+fn backfill_meter(fronius: &Fronius, device_id: &DeviceId, tags: &DeviceTags, start: OffsetDateTime, end: OffsetDateTime) -> Result<Vec<BackfillMeterData>, Box<dyn std::error::Error>> {
+    let scope = ArchiveScope::Device(DeviceType::Meter, *device_id);
+    let rows = read_archive(fronius, &scope, &[
+        ArchiveChannel::CurrentAcPhase1,
+        ArchiveChannel::CurrentAcPhase2,
+        ArchiveChannel::CurrentAcPhase3,
+        ArchiveChannel::VoltageAcPhase1,
+        ArchiveChannel::VoltageAcPhase2,
+        ArchiveChannel::VoltageAcPhase3,
+        ArchiveChannel::PowerRealPacSum,
+    ], start, end)?;
+
+    let data = rows.iter().map(|(time, row)| BackfillMeterData {
+        device: "Meter".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
+        backfilled: "true".to_owned(),
+        l1_current: row.get(&ArchiveChannel::CurrentAcPhase1).copied(),
+        l2_current: row.get(&ArchiveChannel::CurrentAcPhase2).copied(),
+        l3_current: row.get(&ArchiveChannel::CurrentAcPhase3).copied(),
+        l1_voltage: row.get(&ArchiveChannel::VoltageAcPhase1).copied(),
+        l2_voltage: row.get(&ArchiveChannel::VoltageAcPhase2).copied(),
+        l3_voltage: row.get(&ArchiveChannel::VoltageAcPhase3).copied(),
+        power: row.get(&ArchiveChannel::PowerRealPacSum).copied(),
+        time: timestamp(time),
+    }).collect();
+    Ok(data)
+}
+
+fn backfill_storage(fronius: &Fronius, device_id: &DeviceId, tags: &DeviceTags, start: OffsetDateTime, end: OffsetDateTime) -> Result<Vec<BackfillStorageData>, Box<dyn std::error::Error>> {
+    let scope = ArchiveScope::Device(DeviceType::Storage, *device_id);
+    let rows = read_archive(fronius, &scope, &[
+        ArchiveChannel::StateOfChargeRelative,
+        ArchiveChannel::CurrentDc,
+        ArchiveChannel::VoltageDc,
+        ArchiveChannel::TemperatureCell,
+    ], start, end)?;
+
+    let data = rows.iter().map(|(time, row)| BackfillStorageData {
+        device: "Storage".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
+        backfilled: "true".to_owned(),
+        charge_percentage: row.get(&ArchiveChannel::StateOfChargeRelative).copied(),
+        dc_current: row.get(&ArchiveChannel::CurrentDc).copied(),
+        dc_voltage: row.get(&ArchiveChannel::VoltageDc).copied(),
+        temperature_cell: row.get(&ArchiveChannel::TemperatureCell).copied(),
+        time: timestamp(time),
+    }).collect();
+    Ok(data)
+}
+
+fn write(influx: &InfluxSink, data: &[impl influxdb2::models::WriteDataPoint], tags: &[(String, String)]) {
+    let res = data
+        .iter()
+        .map(influx::line)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| error.into())
+        .and_then(|lines| influx.write_lines(lines, tags));
+    if let Err(error) = res {
+        println!("Error during influxdb write occured: {:?}", error);
+    }
+}
+
+/// Returns the start of the gap of one device, i.e. the last written timestamp or, if
+/// nothing was written recently, the oldest point the archive can still deliver.
+fn gap_start(last: Option<OffsetDateTime>, max_days: i64, now: OffsetDateTime) -> OffsetDateTime {
+    let oldest = now - Duration::days(max_days);
+    last.map_or(oldest, |last| std::cmp::max(last, oldest))
+}
+
+/// The tags identifying the points of one device in `measurement`, as written by the
+/// poll loop.
+fn device_filter(tags: &[(String, String)], device: &str, device_id: &DeviceId) -> Vec<(String, String)> {
+    let mut filter = tags.to_vec();
+    filter.push(("device".to_owned(), device.to_owned()));
+    filter.push(("device_id".to_owned(), u8::from(device_id).to_string()));
+    filter
+}
+
+/// Fills the gap between the last point written to the `inverter`, `meter` and
+/// `storage` measurements and now with data from the Datamanager archive. The gap is
+/// looked up per device.
+///
+/// Backfilled points carry the same tags as the points written by the poll loop and the
+/// additional tag `backfilled=true`. The backfill looks back at most `max_days` days.
+pub fn run(fronius: &Fronius, influx: &InfluxSink, inventory: &Inventory, tags: &[(String, String)], max_days: i64) -> Result<(), Box<dyn std::error::Error>> {
+    let now = OffsetDateTime::now_utc();
+
+    let infos = fronius.get_inverter_info().ok();
+    for inverter_id in inventory.devices(DeviceType::Inverter) {
+        let last = last_written(influx, "inverter", &device_filter(tags, "Inverter", inverter_id), max_days)?;
+        let start = gap_start(last, max_days, now);
+        let inverter_tags = get_inverter_tags(inventory, infos.as_ref(), inverter_id);
+        match backfill_inverter(fronius, inverter_id, &inverter_tags, start, now) {
+            Ok((data, mppt_data)) => {
+                println!("Backfilling {} points of inverter {} since {start}", data.len() + mppt_data.len(), u8::from(inverter_id));
+                write(influx, &data, tags);
+                write(influx, &mppt_data, tags);
+            }
+            Err(error) => println!("Error during backfill of inverter occured: {:?}", error),
+        }
+    }
+
+    for meter_id in inventory.devices(DeviceType::Meter) {
+        let last = last_written(influx, "meter", &device_filter(tags, "Meter", meter_id), max_days)?;
+        let start = gap_start(last, max_days, now);
+        let meter_tags = get_device_tags(inventory, DeviceType::Meter, meter_id);
+        match backfill_meter(fronius, meter_id, &meter_tags, start, now) {
+            Ok(data) => {
+                println!("Backfilling {} points of meter {} since {start}", data.len(), u8::from(meter_id));
+                write(influx, &data, tags);
+            }
+            Err(error) => println!("Error during backfill of meter occured: {:?}", error),
+        }
+    }
+
+    for storage_id in inventory.devices(DeviceType::Storage) {
+        let last = last_written(influx, "storage", &device_filter(tags, "Storage", storage_id), max_days)?;
+        let start = gap_start(last, max_days, now);
+        let storage_tags = get_device_tags(inventory, DeviceType::Storage, storage_id);
+        match backfill_storage(fronius, storage_id, &storage_tags, start, now) {
+            Ok(data) => {
+                println!("Backfilling {} points of storage {} since {start}", data.len(), u8::from(storage_id));
+                write(influx, &data, tags);
+            }
+            Err(error) => println!("Error during backfill of storage occured: {:?}", error),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn archive(json: serde_json::Value) -> ArchiveData {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn merge_archive_joins_channels_by_timestamp() {
+        let data = archive(serde_json::json!({
+            "meter:1": {
+                "Start": "2024-05-01T00:00:00+00:00",
+                "End": "2024-05-01T23:59:59+00:00",
+                "Data": {
+                    "PowerReal_PAC_Sum": { "Unit": "W", "Values": { "0": 100.0, "300": 200.0, "600": 300.0 } },
+                    "Current_AC_Phase_1": { "Unit": "A", "Values": { "300": 1.5, "900": 2.5 } },
+                    "Voltage_AC_Phase_1": { "Unit": "V", "Values": { "300": 230.0 } }
+                }
+            }
+        }));
+        let channels = [ArchiveChannel::PowerRealPacSum, ArchiveChannel::CurrentAcPhase1];
+        let mut rows = ArchiveRows::new();
+        merge_archive(&mut rows, &data, &channels, datetime!(2024-05-01 0:00 UTC), datetime!(2024-05-01 1:00 UTC));
+
+        let times: Vec<_> = rows.keys().copied().collect();
+        // The first sample is at the start of the gap, which is already written
+        assert_eq!(times, [datetime!(2024-05-01 0:05 UTC), datetime!(2024-05-01 0:10 UTC), datetime!(2024-05-01 0:15 UTC)]);
+
+        let row = &rows[&datetime!(2024-05-01 0:05 UTC)];
+        assert_eq!(row.get(&ArchiveChannel::PowerRealPacSum), Some(&200.0));
+        assert_eq!(row.get(&ArchiveChannel::CurrentAcPhase1), Some(&1.5));
+        // Channels that were not requested are not merged
+        assert_eq!(row.get(&ArchiveChannel::VoltageAcPhase1), None);
+
+        let row = &rows[&datetime!(2024-05-01 0:15 UTC)];
+        assert_eq!(row.get(&ArchiveChannel::PowerRealPacSum), None);
+        assert_eq!(row.get(&ArchiveChannel::CurrentAcPhase1), Some(&2.5));
+    }
+
+    #[test]
+    fn merge_archive_drops_values_outside_of_the_gap() {
+        let data = archive(serde_json::json!({
+            "inverter/1": {
+                "Start": "2024-05-01T00:00:00+00:00",
+                "End": "2024-05-01T23:59:59+00:00",
+                "Data": {
+                    "PowerReal_PAC_Sum": { "Unit": "W", "Values": { "0": 1.0, "300": 2.0, "600": 3.0, "900": 4.0 } }
+                }
+            }
+        }));
+        let mut rows = ArchiveRows::new();
+        merge_archive(&mut rows, &data, &[ArchiveChannel::PowerRealPacSum], datetime!(2024-05-01 0:00 UTC), datetime!(2024-05-01 0:10 UTC));
+
+        let values: Vec<_> = rows.values().map(|row| row[&ArchiveChannel::PowerRealPacSum]).collect();
+        assert_eq!(values, [2.0, 3.0]);
+    }
+
+    #[test]
+    fn merge_archive_keeps_rows_of_previous_chunks() {
+        let first = archive(serde_json::json!({
+            "inverter/1": {
+                "Start": "2024-05-01T00:00:00+00:00",
+                "End": "2024-05-01T23:59:59+00:00",
+                "Data": { "PowerReal_PAC_Sum": { "Unit": "W", "Values": { "86100": 1.0 } } }
+            }
+        }));
+        let second = archive(serde_json::json!({
+            "inverter/1": {
+                "Start": "2024-05-02T00:00:00+00:00",
+                "End": "2024-05-02T23:59:59+00:00",
+                "Data": { "PowerReal_PAC_Sum": { "Unit": "W", "Values": { "300": 2.0 } } }
+            }
+        }));
+        let (start, end) = (datetime!(2024-05-01 0:00 UTC), datetime!(2024-05-03 0:00 UTC));
+        let mut rows = ArchiveRows::new();
+        merge_archive(&mut rows, &first, &[ArchiveChannel::PowerRealPacSum], start, end);
+        merge_archive(&mut rows, &second, &[ArchiveChannel::PowerRealPacSum], start, end);
+
+        let times: Vec<_> = rows.keys().copied().collect();
+        assert_eq!(times, [datetime!(2024-05-01 23:55 UTC), datetime!(2024-05-02 0:05 UTC)]);
+    }
+
+    #[test]
+    fn gap_starts_at_last_written_point() {
+        let now = datetime!(2024-05-20 12:00 UTC);
+        let last = datetime!(2024-05-19 8:30 UTC);
+        assert_eq!(gap_start(Some(last), 16, now), last);
+    }
+
+    #[test]
+    fn gap_is_limited_to_max_days() {
+        let now = datetime!(2024-05-20 12:00 UTC);
+        assert_eq!(gap_start(None, 16, now), datetime!(2024-05-04 12:00 UTC));
+        assert_eq!(gap_start(Some(datetime!(2024-04-01 0:00 UTC)), 2, now), datetime!(2024-05-18 12:00 UTC));
+    }
+
+    #[test]
+    fn device_filter_selects_one_device() {
+        let tags = [("location".to_owned(), "home".to_owned())];
+        let filter = device_filter(&tags, "Meter", &DeviceId::try_from(2u8).unwrap());
+        assert_eq!(filter, [
+            ("location".to_owned(), "home".to_owned()),
+            ("device".to_owned(), "Meter".to_owned()),
+            ("device_id".to_owned(), "2".to_owned()),
+        ]);
+    }
+}
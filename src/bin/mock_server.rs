@@ -0,0 +1,13 @@
+use fronius_api::mock::MockServer;
+
+/// Serves the fixtures in the given directory (default `tests/fixtures`) like a
+/// Fronius device, on the address in `MOCK_ADDR` (default `127.0.0.1:8080`).
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let fixture_dir = std::env::args().nth(1).unwrap_or("tests/fixtures".to_owned());
+    let addr = std::env::var("MOCK_ADDR").unwrap_or("127.0.0.1:8080".to_owned());
+
+    let server = MockServer::bind(&addr, &fixture_dir)?;
+    println!("Serving {fixture_dir} on {}", server.url());
+    server.wait();
+    Ok(())
+}
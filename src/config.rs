@@ -0,0 +1,610 @@
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use fronius_api::fronius::{self, Capability, Fronius, RetryPolicy};
+use serde::Deserialize;
+
+use crate::mqtt::PayloadFormat;
+
+/// The config file used if `CONFIG_FILE` is not set, it is optional.
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+const DEFAULT_POLL_INTERVAL: u64 = 15;
+const DEFAULT_TIMEOUT: u64 = 10;
+const DEFAULT_RETRIES: u32 = 2;
+/// The Datamanager only answers archive requests for up to 16 days, so the backfill
+/// never looks further back than this by default.
+const DEFAULT_BACKFILL_MAX_DAYS: i64 = 16;
+
+/// The data read from a Fronius system, named like the InfluxDB measurement it is
+/// written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Endpoint {
+    Inverter,
+    InverterPhase,
+    InverterPeak,
+    InverterInfo,
+    LoggerInfo,
+    Meter,
+    Storage,
+    OhmPilot,
+    Sensor,
+    StringControl,
+    PowerFlow,
+}
+
+impl Endpoint {
+    pub const ALL: [Endpoint; 11] = [
+        Endpoint::Inverter,
+        Endpoint::InverterPhase,
+        Endpoint::InverterPeak,
+        Endpoint::InverterInfo,
+        Endpoint::LoggerInfo,
+        Endpoint::Meter,
+        Endpoint::Storage,
+        Endpoint::OhmPilot,
+        Endpoint::Sensor,
+        Endpoint::StringControl,
+        Endpoint::PowerFlow,
+    ];
+
+    fn all() -> Vec<Endpoint> {
+        Self::ALL.to_vec()
+    }
+
+    /// The data collection the endpoint is read from.
+    pub fn capability(self) -> Capability {
+        match self {
+            Endpoint::Inverter => Capability::CommonInverterData,
+            Endpoint::InverterPhase => Capability::ThreePhaseInverterData,
+            Endpoint::InverterPeak => Capability::MinMaxInverterData,
+            Endpoint::InverterInfo => Capability::InverterInfo,
+            Endpoint::LoggerInfo => Capability::LoggerInfo,
+            Endpoint::Meter => Capability::MeterRealtimeData,
+            Endpoint::Storage => Capability::StorageRealtimeData,
+            Endpoint::OhmPilot => Capability::OhmPilotRealtimeData,
+            Endpoint::Sensor => Capability::NowSensorData,
+            Endpoint::StringControl => Capability::NowStringControlData,
+            Endpoint::PowerFlow => Capability::PowerFlowRealtimeData,
+        }
+    }
+
+    /// The name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Endpoint::Inverter => "inverter",
+            Endpoint::InverterPhase => "inverter_phase",
+            Endpoint::InverterPeak => "inverter_peak",
+            Endpoint::InverterInfo => "inverter_info",
+            Endpoint::LoggerInfo => "logger_info",
+            Endpoint::Meter => "meter",
+            Endpoint::Storage => "storage",
+            Endpoint::OhmPilot => "ohm_pilot",
+            Endpoint::Sensor => "sensor",
+            Endpoint::StringControl => "string_control",
+            Endpoint::PowerFlow => "power_flow",
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default, rename = "system")]
+    pub systems: Vec<SystemConfig>,
+    pub influxdb: Option<InfluxConfig>,
+    pub prometheus: Option<PrometheusConfig>,
+    pub mqtt: Option<MqttConfig>,
+    #[serde(default)]
+    pub backfill: BackfillConfig,
+}
+
+/// Which time the polled points are stamped with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampSource {
+    /// The time the device reports for its data, the local time if it reports none.
+    #[default]
+    Device,
+    /// The local time when the data was received, e.g. if the clock of the device is off.
+    Local,
+}
+
+/// A single Datamanager or GEN24 inverter.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SystemConfig {
+    /// Written as `system` tag/label to tell the data of several systems apart.
+    pub name: Option<String>,
+    /// Hostname, IPv4 or IPv6 address of the device, optionally with port, or its URL.
+    pub host: String,
+    /// Seconds between two polls.
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+    /// Seconds until a single request to the device times out.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// How often a failed request is retried.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Additional tags written with every point of this system.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default = "Endpoint::all")]
+    pub endpoints: Vec<Endpoint>,
+    #[serde(default)]
+    pub timestamps: TimestampSource,
+}
+
+fn default_poll_interval() -> u64 {
+    DEFAULT_POLL_INTERVAL
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_TIMEOUT
+}
+
+fn default_retries() -> u32 {
+    DEFAULT_RETRIES
+}
+
+impl SystemConfig {
+    fn new(host: String) -> Self {
+        Self {
+            name: None,
+            host,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            tags: BTreeMap::new(),
+            endpoints: Endpoint::all(),
+            timestamps: TimestampSource::default(),
+        }
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval)
+    }
+
+    /// Connects to the device of this system.
+    pub fn connect(&self) -> Result<Fronius, fronius::Error> {
+        Fronius::builder(&self.host)?
+            .timeout(Duration::from_secs(self.timeout))
+            .retry_policy(RetryPolicy {
+                max_retries: self.retries,
+                ..RetryPolicy::default()
+            })
+            .build()
+    }
+
+    pub fn is_enabled(&self, endpoint: Endpoint) -> bool {
+        self.endpoints.contains(&endpoint)
+    }
+
+    /// Returns the tags of every point of this system, including the `system` tag if the
+    /// system is named.
+    pub fn all_tags(&self) -> Vec<(String, String)> {
+        let mut tags: Vec<(String, String)> = self.name.iter().map(|name| ("system".to_owned(), name.to_owned())).collect();
+        tags.extend(self.tags.iter().map(|(key, value)| (key.to_owned(), value.to_owned())));
+        tags
+    }
+
+    /// Returns a short description for log messages.
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.host)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InfluxConfig {
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub org: String,
+    #[serde(default)]
+    pub token: String,
+    #[serde(default)]
+    pub bucket: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrometheusConfig {
+    #[serde(default)]
+    pub addr: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    #[serde(default)]
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub topic_prefix: String,
+    #[serde(default = "default_mqtt_format")]
+    pub format: PayloadFormat,
+    #[serde(default = "default_true")]
+    pub retain: bool,
+    #[serde(default)]
+    pub homeassistant: bool,
+    #[serde(default = "default_homeassistant_prefix")]
+    pub homeassistant_prefix: String,
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "froniousAPI".to_owned()
+}
+
+fn default_mqtt_topic_prefix() -> String {
+    "fronius".to_owned()
+}
+
+fn default_mqtt_format() -> PayloadFormat {
+    PayloadFormat::Json
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_homeassistant_prefix() -> String {
+    "homeassistant".to_owned()
+}
+
+/// Filling gaps in InfluxDB from the archive of the Datamanager.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackfillConfig {
+    /// Run the backfill for every system before the poll loop starts.
+    #[serde(default)]
+    pub on_startup: bool,
+    /// Days the backfill looks back at most.
+    #[serde(default = "default_backfill_max_days")]
+    pub max_days: i64,
+}
+
+fn default_backfill_max_days() -> i64 {
+    DEFAULT_BACKFILL_MAX_DAYS
+}
+
+impl Default for BackfillConfig {
+    fn default() -> Self {
+        Self {
+            on_startup: false,
+            max_days: DEFAULT_BACKFILL_MAX_DAYS,
+        }
+    }
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: default_mqtt_port(),
+            username: None,
+            password: None,
+            client_id: default_mqtt_client_id(),
+            topic_prefix: default_mqtt_topic_prefix(),
+            format: default_mqtt_format(),
+            retain: true,
+            homeassistant: false,
+            homeassistant_prefix: default_homeassistant_prefix(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file given by `CONFIG_FILE` (or `config.toml` if it exists) and
+    /// applies the environment variable overrides.
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut config = match std::env::var("CONFIG_FILE") {
+            Ok(path) => Self::read(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::read(Path::new(DEFAULT_CONFIG_FILE))?,
+            Err(_) => Config::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads a TOML or, if the file ends with `.yaml` or `.yml`, YAML config file.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path).map_err(|error| format!("Could not read {}: {error}", path.display()))?;
+        let config = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&content)?,
+            _ => toml::from_str(&content)?,
+        };
+        Ok(config)
+    }
+
+    /// Overrides the config with the environment variables used before config files
+    /// existed, so Docker setups keep working. `env` looks up a single variable.
+    fn apply_env(&mut self, env: impl Fn(&str) -> Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(host) = env("FRONIUS_IP") {
+            match self.systems.first_mut() {
+                Some(system) => system.host = host,
+                None => self.systems.push(SystemConfig::new(host)),
+            }
+        }
+
+        if env("INFLUX_DB_URL").is_some() && self.influxdb.is_none() {
+            self.influxdb = Some(InfluxConfig::default());
+        }
+        if let Some(influxdb) = &mut self.influxdb {
+            override_env(&env, &mut influxdb.url, "INFLUX_DB_URL");
+            override_env(&env, &mut influxdb.org, "INFLUX_DB_ORG");
+            override_env(&env, &mut influxdb.token, "INFLUX_DB_TOKEN");
+            override_env(&env, &mut influxdb.bucket, "INFLUX_DB_BUCKET");
+        }
+
+        if let Some(addr) = env("PROMETHEUS_ADDR") {
+            self.prometheus = Some(PrometheusConfig { addr });
+        }
+
+        if env("MQTT_HOST").is_some() && self.mqtt.is_none() {
+            self.mqtt = Some(MqttConfig::default());
+        }
+        if let Some(mqtt) = &mut self.mqtt {
+            override_env(&env, &mut mqtt.host, "MQTT_HOST");
+            override_env(&env, &mut mqtt.client_id, "MQTT_CLIENT_ID");
+            override_env(&env, &mut mqtt.topic_prefix, "MQTT_TOPIC_PREFIX");
+            override_env(&env, &mut mqtt.homeassistant_prefix, "MQTT_HOMEASSISTANT_PREFIX");
+            if let Some(port) = env("MQTT_PORT") {
+                mqtt.port = port.parse()?;
+            }
+            if let Some(username) = env("MQTT_USERNAME") {
+                mqtt.username = Some(username);
+            }
+            if let Some(password) = env("MQTT_PASSWORD") {
+                mqtt.password = Some(password);
+            }
+            if let Some(format) = env("MQTT_FORMAT") {
+                mqtt.format = match format.as_str() {
+                    "json" => PayloadFormat::Json,
+                    "values" => PayloadFormat::Values,
+                    format => return Err(format!("Unknown MQTT_FORMAT '{format}', expected 'json' or 'values'").into()),
+                };
+            }
+            if let Some(retain) = env("MQTT_RETAIN") {
+                mqtt.retain = retain != "false";
+            }
+            if let Some(homeassistant) = env("MQTT_HOMEASSISTANT") {
+                mqtt.homeassistant = homeassistant == "true";
+            }
+        }
+
+        if let Some(on_startup) = env("BACKFILL_ON_STARTUP") {
+            self.backfill.on_startup = on_startup == "true";
+        }
+        if let Some(max_days) = env("BACKFILL_MAX_DAYS") {
+            self.backfill.max_days = max_days.parse()?;
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.systems.is_empty() {
+            return Err("No Fronius system configured, set FRONIUS_IP or add a [[system]] to the config file".into());
+        }
+        let mut names = std::collections::HashSet::new();
+        for system in &self.systems {
+            if system.poll_interval == 0 {
+                return Err(format!("poll_interval of system {} must be at least 1 second", system.label()).into());
+            }
+            if system.timeout == 0 {
+                return Err(format!("timeout of system {} must be at least 1 second", system.label()).into());
+            }
+            if !names.insert(system.name.as_deref()) {
+                return Err("Every system needs a unique name if more than one system is configured".into());
+            }
+        }
+        if let Some(influxdb) = &self.influxdb {
+            for (value, name) in [(&influxdb.url, "url"), (&influxdb.org, "org"), (&influxdb.token, "token"), (&influxdb.bucket, "bucket")] {
+                if value.is_empty() {
+                    return Err(format!("InfluxDB {name} is missing").into());
+                }
+            }
+        }
+        if self.mqtt.as_ref().is_some_and(|mqtt| mqtt.host.is_empty()) {
+            return Err("MQTT host is missing".into());
+        }
+        if self.backfill.max_days < 1 {
+            return Err("backfill max_days must be at least 1 day".into());
+        }
+        Ok(())
+    }
+}
+
+fn override_env(env: &impl Fn(&str) -> Option<String>, value: &mut String, name: &str) {
+    if let Some(env) = env(name) {
+        *value = env;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Config {
+        toml::from_str(content).unwrap()
+    }
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+    }
+
+    fn validation_error(config: &Config) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn example_config_is_valid() {
+        let config = Config::read(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/config.example.toml"))).unwrap();
+        config.validate().unwrap();
+
+        assert_eq!(config.systems.len(), 2);
+        let home = &config.systems[0];
+        assert_eq!(home.name.as_deref(), Some("home"));
+        assert_eq!(home.host, "10.0.0.1");
+        assert_eq!(home.timestamps, TimestampSource::Device);
+        assert_eq!(home.all_tags(), [("system".to_owned(), "home".to_owned()), ("site".to_owned(), "vienna".to_owned())]);
+        assert_eq!(home.endpoints, Endpoint::all());
+        let office = &config.systems[1];
+        assert_eq!(office.poll_interval, 60);
+        assert_eq!(office.endpoints, [Endpoint::Inverter, Endpoint::Meter, Endpoint::PowerFlow]);
+        assert!(!office.is_enabled(Endpoint::Storage));
+
+        assert_eq!(config.influxdb.unwrap().bucket, "<bucket>");
+        assert_eq!(config.prometheus.unwrap().addr, "0.0.0.0:9184");
+        assert_eq!(config.mqtt.unwrap().format, PayloadFormat::Json);
+        assert!(!config.backfill.on_startup);
+        assert_eq!(config.backfill.max_days, 16);
+    }
+
+    #[test]
+    fn defaults_are_applied() {
+        let config = parse("[[system]]\nhost = \"fronius.local\"\n\n[mqtt]\nhost = \"broker\"\n");
+        let system = &config.systems[0];
+        assert_eq!(system.name, None);
+        assert_eq!(system.poll_interval, DEFAULT_POLL_INTERVAL);
+        assert_eq!(system.timeout, DEFAULT_TIMEOUT);
+        assert_eq!(system.retries, DEFAULT_RETRIES);
+        assert_eq!(system.endpoints, Endpoint::all());
+        assert!(system.all_tags().is_empty());
+
+        let mqtt = config.mqtt.as_ref().unwrap();
+        assert_eq!(mqtt.port, 1883);
+        assert_eq!(mqtt.topic_prefix, "fronius");
+        assert!(mqtt.retain);
+        assert!(!mqtt.homeassistant);
+        assert!(config.influxdb.is_none());
+        assert_eq!(config.backfill.max_days, DEFAULT_BACKFILL_MAX_DAYS);
+    }
+
+    #[test]
+    fn yaml_config_is_parsed() {
+        let config: Config = serde_yaml::from_str(
+            "system:\n  - name: home\n    host: 10.0.0.1\n    timestamps: local\n    endpoints: [meter, storage]\nbackfill:\n  on_startup: true\n  max_days: 3\n",
+        )
+        .unwrap();
+        let system = &config.systems[0];
+        assert_eq!(system.host, "10.0.0.1");
+        assert_eq!(system.timestamps, TimestampSource::Local);
+        assert_eq!(system.endpoints, [Endpoint::Meter, Endpoint::Storage]);
+        assert!(config.backfill.on_startup);
+        assert_eq!(config.backfill.max_days, 3);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!(toml::from_str::<Config>("[[system]]\nhost = \"10.0.0.1\"\npoll_intervall = 5\n").is_err());
+        assert!(toml::from_str::<Config>("[[system]]\nhost = \"10.0.0.1\"\nendpoints = [\"battery\"]\n").is_err());
+    }
+
+    #[test]
+    fn validate_rejects_invalid_configs() {
+        assert!(validation_error(&Config::default()).starts_with("No Fronius system configured"));
+        assert_eq!(
+            validation_error(&parse("[[system]]\nname = \"a\"\nhost = \"10.0.0.1\"\npoll_interval = 0\n")),
+            "poll_interval of system a must be at least 1 second"
+        );
+        assert_eq!(
+            validation_error(&parse("[[system]]\nhost = \"10.0.0.1\"\ntimeout = 0\n")),
+            "timeout of system 10.0.0.1 must be at least 1 second"
+        );
+        assert_eq!(
+            validation_error(&parse("[[system]]\nhost = \"10.0.0.1\"\n\n[[system]]\nhost = \"10.0.0.2\"\n")),
+            "Every system needs a unique name if more than one system is configured"
+        );
+        assert_eq!(
+            validation_error(&parse("[[system]]\nhost = \"10.0.0.1\"\n\n[influxdb]\nurl = \"http://influx:8086\"\norg = \"org\"\nbucket = \"bucket\"\n")),
+            "InfluxDB token is missing"
+        );
+        assert_eq!(validation_error(&parse("[[system]]\nhost = \"10.0.0.1\"\n\n[mqtt]\nport = 1883\n")), "MQTT host is missing");
+        assert_eq!(
+            validation_error(&parse("[[system]]\nhost = \"10.0.0.1\"\n\n[backfill]\nmax_days = 0\n")),
+            "backfill max_days must be at least 1 day"
+        );
+    }
+
+    #[test]
+    fn environment_overrides_config_file() {
+        let mut config = parse(
+            "[[system]]\nname = \"home\"\nhost = \"10.0.0.1\"\n\n[[system]]\nname = \"office\"\nhost = \"10.0.0.2\"\n\n\
+             [influxdb]\nurl = \"http://file:8086\"\norg = \"org\"\ntoken = \"file\"\nbucket = \"bucket\"\n\n\
+             [mqtt]\nhost = \"file\"\nformat = \"values\"\n",
+        );
+        config
+            .apply_env(env(&[
+                ("FRONIUS_IP", "10.0.0.9"),
+                ("INFLUX_DB_TOKEN", "env"),
+                ("MQTT_PORT", "8883"),
+                ("MQTT_FORMAT", "json"),
+                ("MQTT_RETAIN", "false"),
+                ("BACKFILL_ON_STARTUP", "true"),
+                ("BACKFILL_MAX_DAYS", "5"),
+            ]))
+            .unwrap();
+
+        // Only the first system is overridden by FRONIUS_IP
+        assert_eq!(config.systems[0].host, "10.0.0.9");
+        assert_eq!(config.systems[1].host, "10.0.0.2");
+        let influxdb = config.influxdb.as_ref().unwrap();
+        assert_eq!(influxdb.url, "http://file:8086");
+        assert_eq!(influxdb.token, "env");
+        let mqtt = config.mqtt.as_ref().unwrap();
+        assert_eq!(mqtt.host, "file");
+        assert_eq!(mqtt.port, 8883);
+        assert_eq!(mqtt.format, PayloadFormat::Json);
+        assert!(!mqtt.retain);
+        assert!(config.backfill.on_startup);
+        assert_eq!(config.backfill.max_days, 5);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn environment_alone_configures_exporter() {
+        let mut config = Config::default();
+        config
+            .apply_env(env(&[
+                ("FRONIUS_IP", "10.0.0.1"),
+                ("INFLUX_DB_URL", "http://influx:8086"),
+                ("INFLUX_DB_ORG", "org"),
+                ("INFLUX_DB_TOKEN", "token"),
+                ("INFLUX_DB_BUCKET", "bucket"),
+                ("PROMETHEUS_ADDR", "0.0.0.0:9184"),
+                ("MQTT_HOST", "broker"),
+                ("MQTT_HOMEASSISTANT", "true"),
+            ]))
+            .unwrap();
+        config.validate().unwrap();
+
+        assert_eq!(config.systems.len(), 1);
+        assert_eq!(config.systems[0].host, "10.0.0.1");
+        assert_eq!(config.influxdb.as_ref().unwrap().url, "http://influx:8086");
+        assert_eq!(config.prometheus.as_ref().unwrap().addr, "0.0.0.0:9184");
+        let mqtt = config.mqtt.as_ref().unwrap();
+        assert_eq!(mqtt.host, "broker");
+        assert!(mqtt.homeassistant);
+        assert!(!config.backfill.on_startup);
+    }
+
+    #[test]
+    fn invalid_environment_is_rejected() {
+        let mut config = parse("[[system]]\nhost = \"10.0.0.1\"\n\n[mqtt]\nhost = \"broker\"\n");
+        let error = config.apply_env(env(&[("MQTT_FORMAT", "xml")])).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown MQTT_FORMAT 'xml', expected 'json' or 'values'");
+        assert!(config.apply_env(env(&[("MQTT_PORT", "mqtt")])).is_err());
+        assert!(config.apply_env(env(&[("BACKFILL_MAX_DAYS", "two")])).is_err());
+    }
+}
@@ -1,7 +1,15 @@
 use reqwest::{blocking::Client, Url};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use std::{borrow::Borrow, collections::HashMap, net::IpAddr};
+use rand::Rng;
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use thiserror::Error;
 use time::OffsetDateTime;
 
@@ -11,48 +19,331 @@ pub enum Error {
     UnsupportedApiVersion(u64),
     #[error("invalid endpoint {0:?}")]
     InvalidEndpoint(String),
+    #[error("invalid device address {0:?}")]
+    InvalidAddress(String),
     #[error("request failed")]
     Request(#[from] reqwest::Error),
     #[error("decoding response body failed")]
     Decode(#[from] serde_json::Error),
     #[error("received error response {:?}: {}", .0.code, .0.reason)]
     Response(Status),
+    #[error("invalid archive range {0} - {1}")]
+    InvalidArchiveRange(OffsetDateTime, OffsetDateTime),
+}
+
+/// How a failed request should be treated by the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The request may succeed if repeated, e.g. after a network error or timeout.
+    Transient,
+    /// The device does not implement or support the request, repeating it is pointless.
+    Unsupported,
+    /// Any other error, e.g. invalid arguments or responses that cannot be decoded.
+    Permanent,
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Request(error) if error.is_decode() || error.is_builder() => ErrorKind::Permanent,
+            Error::Request(_) => ErrorKind::Transient,
+            Error::Response(status) => status.code.kind(),
+            Error::UnsupportedApiVersion(_) => ErrorKind::Unsupported,
+            _ => ErrorKind::Permanent,
+        }
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.kind() == ErrorKind::Transient
+    }
+
+    pub fn is_unsupported(&self) -> bool {
+        self.kind() == ErrorKind::Unsupported
+    }
+
+    pub fn is_permanent(&self) -> bool {
+        self.kind() == ErrorKind::Permanent
+    }
+
+    /// Returns the status reported by the device, if it answered with an error.
+    pub fn status(&self) -> Option<&Status> {
+        match self {
+            Error::Response(status) => Some(status),
+            _ => None,
+        }
+    }
+}
+
+/// How often and how fast failed requests are retried.
+///
+/// Only [transient](ErrorKind::Transient) errors are retried, i.e. requests failing with
+/// [`Error::Request`] or one of the status codes [`StatusCode::Timeout`],
+/// [`StatusCode::LNRequestTimeout`] and [`StatusCode::DeviceNotAvailable`].
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt, `0` disables retries.
+    pub max_retries: u32,
+    /// Backoff before the first retry, doubled for every further retry.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the time to wait before retry number `retry`, starting at 0.
+    ///
+    /// The exponential backoff is randomized to between half and all of it, so several
+    /// clients failing at the same time do not retry in lockstep.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff);
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    fn retry<T>(&self, mut request: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+        let mut retry = 0;
+        loop {
+            match request() {
+                Err(error) if error.is_transient() && retry < self.max_retries => {
+                    std::thread::sleep(self.backoff(retry));
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn retry_async<T, F>(&self, mut request: impl FnMut() -> F) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        let mut retry = 0;
+        loop {
+            match request().await {
+                Err(error) if error.is_transient() && retry < self.max_retries => {
+                    tokio::time::sleep(self.backoff(retry)).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+const DEFAULT_USER_AGENT: &str = concat!("froniousAPI/", env!("CARGO_PKG_VERSION"));
+
+/// Describes how to reach a Fronius device and connects to it.
+///
+/// The device can be given as IP address, hostname or URL. A URL may use HTTPS, a
+/// non-default port and a path, e.g. for a device behind a reverse proxy:
+///
+/// ```no_run
+/// # use fronius_api::fronius::FroniusBuilder;
+/// let fronius = FroniusBuilder::new("fronius.local")?.build()?;
+/// let fronius = FroniusBuilder::new("[fd00::10]:8080")?.build()?;
+/// let fronius = FroniusBuilder::new("https://proxy.example.com/fronius/")?.build()?;
+/// # Ok::<(), fronius_api::fronius::Error>(())
+/// ```
+///
+/// By default requests time out after 10 seconds and failed requests are retried twice,
+/// see [`RetryPolicy`].
+#[derive(Debug, Clone)]
+pub struct FroniusBuilder {
+    url: Url,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    retry_policy: RetryPolicy,
+    user_agent: String,
+}
+
+impl FroniusBuilder {
+    /// Parses the address of a device, which is either a URL, a hostname or an IP
+    /// address, each optionally followed by a port.
+    pub fn new(address: &str) -> Result<Self, Error> {
+        Ok(Self::from_url(device_url_from_str(address)?))
+    }
+
+    pub fn from_ip(ip: IpAddr) -> Self {
+        Self::from_url(device_url(ip))
+    }
+
+    pub fn from_url(mut url: Url) -> Self {
+        // Endpoints are resolved relative to the URL, which only keeps the last path
+        // segment if it ends with a slash.
+        if !url.path().ends_with('/') {
+            let path = format!("{}/", url.path());
+            url.set_path(&path);
+        }
+        Self {
+            url,
+            connect_timeout: Some(Duration::from_secs(5)),
+            timeout: Some(Duration::from_secs(10)),
+            retry_policy: RetryPolicy::default(),
+            user_agent: DEFAULT_USER_AGENT.to_owned(),
+        }
+    }
+
+    /// Returns the URL the device is reached at.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Limits the time to establish a connection, `None` waits forever.
+    pub fn connect_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.connect_timeout = timeout.into();
+        self
+    }
+
+    /// Limits the time of a single request, from connecting until the response is read
+    /// completely. `None` waits forever.
+    pub fn timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.timeout = timeout.into();
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the `User-Agent` header, `froniousAPI/<version>` by default.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    pub fn build(self) -> Result<Fronius, Error> {
+        let mut client = Client::builder()
+            .user_agent(&self.user_agent)
+            .timeout(self.timeout);
+        if let Some(connect_timeout) = self.connect_timeout {
+            client = client.connect_timeout(connect_timeout);
+        }
+        let client = client.build()?;
+
+        let url = api_version_url(&self.url)?;
+        let api_version: ApiVersion = self
+            .retry_policy
+            .retry(|| Ok(client.get(url.clone()).send()?.json()?))?;
+
+        Ok(Fronius {
+            base_url: api_base_url(&self.url, api_version)?,
+            client,
+            retry_policy: self.retry_policy,
+            timestamp: None,
+        })
+    }
+
+    pub async fn build_async(self) -> Result<AsyncFronius, Error> {
+        let mut client = reqwest::Client::builder().user_agent(&self.user_agent);
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(timeout);
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            client = client.connect_timeout(connect_timeout);
+        }
+        let client = client.build()?;
+
+        let url = api_version_url(&self.url)?;
+        let api_version: ApiVersion = self
+            .retry_policy
+            .retry_async(|| async { Ok(client.get(url.clone()).send().await?.json().await?) })
+            .await?;
+
+        Ok(AsyncFronius {
+            base_url: api_base_url(&self.url, api_version)?,
+            client,
+            retry_policy: self.retry_policy,
+            timestamp: None,
+        })
+    }
+}
+
+/// Receives the timestamp of every response, see [`Fronius::timestamped`].
+type TimestampRecorder = Arc<Mutex<Option<OffsetDateTime>>>;
+
+/// Data together with the time the device reported in the response head.
+#[derive(Debug, Clone)]
+pub struct Timestamped<T> {
+    /// When the device answered, `None` if no request was made.
+    pub timestamp: Option<OffsetDateTime>,
+    pub data: T,
 }
 
 pub struct Fronius {
     client: Client,
     base_url: Url,
+    retry_policy: RetryPolicy,
+    timestamp: Option<TimestampRecorder>,
 }
 
 impl Fronius {
     pub fn connect(ip: IpAddr) -> Result<Self, Error> {
-        let client = Client::new();
-
-        let mut url = reqwest::Url::parse("http://placeholder.local/solar_api/GetAPIVersion.cgi")
-            .expect("Initial base URL should be valid");
-        url.set_ip_host(ip)
-            .expect("Base URL should be a valid base");
-        let api_version: ApiVersion = client.get(url.clone()).send()?.json()?;
+        FroniusBuilder::from_ip(ip).build()
+    }
 
-        if api_version.api_version != 1 {
-            return Err(Error::UnsupportedApiVersion(api_version.api_version));
-        }
+    /// Connects to the device reachable at `url`, e.g. `http://127.0.0.1:8080/`.
+    pub fn connect_url(url: Url) -> Result<Self, Error> {
+        FroniusBuilder::from_url(url).build()
+    }
 
-        url.set_path(&api_version.base_url);
+    /// See [`FroniusBuilder::new`].
+    pub fn builder(address: &str) -> Result<FroniusBuilder, Error> {
+        FroniusBuilder::new(address)
+    }
 
-        Ok(Self {
-            client,
-            base_url: url,
-        })
+    /// Calls `request` and returns its result together with the timestamp from the head
+    /// of the response, i.e. when the device read the data. If `request` sends several
+    /// requests, the timestamp of the last response is returned.
+    ///
+    /// ```no_run
+    /// # use fronius_api::fronius::{DeviceId, Fronius};
+    /// # let fronius = Fronius::builder("fronius.local")?.build()?;
+    /// # let meter_id = DeviceId::try_from(0).unwrap();
+    /// let meter = fronius.timestamped(|fronius| fronius.get_meter_realtime_data_device(&meter_id))?;
+    /// println!("{} W at {:?}", meter.data.power_real_p_sum, meter.timestamp);
+    /// # Ok::<(), fronius_api::fronius::Error>(())
+    /// ```
+    pub fn timestamped<T>(&self, request: impl FnOnce(&Fronius) -> Result<T, Error>) -> Result<Timestamped<T>, Error> {
+        let timestamp = TimestampRecorder::default();
+        let fronius = Fronius {
+            client: self.client.clone(),
+            base_url: self.base_url.clone(),
+            retry_policy: self.retry_policy.clone(),
+            timestamp: Some(timestamp.clone()),
+        };
+        let data = request(&fronius)?;
+        let timestamp = *timestamp.lock().unwrap();
+        Ok(Timestamped { timestamp, data })
     }
 
     fn make_request_inner(&self, url: Url) -> Result<serde_json::Value, Error> {
-        let response: FroniusResponse<serde_json::Value> = self.client.get(url).send()?.json()?;
-
-        if response.head.status.code != StatusCode::Okay {
-            return Err(Error::Response(response.head.status));
+        let response = self.retry_policy.retry(|| {
+            let response: FroniusResponse<serde_json::Value> =
+                self.client.get(url.clone()).send()?.json()?;
+            response.into_ok()
+        })?;
+        if let Some(timestamp) = &self.timestamp {
+            *timestamp.lock().unwrap() = Some(response.head.timestamp);
         }
-
         Ok(response.body)
     }
 
@@ -64,11 +355,7 @@ impl Fronius {
         K: AsRef<str>,
         V: AsRef<str>,
     {
-        let mut url = self
-            .base_url
-            .join(endpoint)
-            .map_err(|_e| Error::InvalidEndpoint(endpoint.to_string()))?;
-        url.query_pairs_mut().extend_pairs(params);
+        let url = endpoint_url(&self.base_url, endpoint, params)?;
         let body = self.make_request_inner(url)?;
 
         Ok(T::deserialize(body)?)
@@ -110,6 +397,18 @@ impl Fronius {
         Ok(response.data)
     }
 
+    pub fn get_logger_info(&self) -> Result<LoggerInfo, Error> {
+        let response: LoggerInfoBody =
+            self.make_request("GetLoggerInfo.cgi", [] as [(&str, &str); 0])?;
+        Ok(response.logger_info)
+    }
+
+    pub fn get_logger_led_info(&self) -> Result<LoggerLedInfo, Error> {
+        let response: CommonResponseBody<_> =
+            self.make_request("GetLoggerLEDInfo.cgi", [] as [(&str, &str); 0])?;
+        Ok(response.data)
+    }
+
     pub fn get_meter_realtime_data_system(&self) -> Result<MeterDataSystem, Error> {
         let response: CommonResponseBody<_> =
             self.make_request("GetMeterRealtimeData.cgi", [("Scope", "System")])?;
@@ -161,11 +460,507 @@ impl Fronius {
         Ok(response.data)
     }
 
+    pub fn get_sensor_realtime_data<C: SensorDataCollection>(
+        &self,
+        device_id: &DeviceId,
+    ) -> Result<C, Error> {
+        let device_id = u8::from(device_id).to_string();
+        let response: CommonResponseBody<_> = self.make_request(
+            "GetSensorRealtimeData.cgi",
+            [
+                ("Scope", "Device"),
+                ("DeviceId", &device_id),
+                ("DataCollection", C::param_value()),
+            ],
+        )?;
+        Ok(response.data)
+    }
+
+    pub fn get_string_realtime_data<C: StringControlDataCollection>(
+        &self,
+        device_id: &DeviceId,
+    ) -> Result<C, Error> {
+        let device_id = u8::from(device_id).to_string();
+        let response: CommonResponseBody<_> = self.make_request(
+            "GetStringRealtimeData.cgi",
+            [
+                ("Scope", "Device"),
+                ("DeviceId", &device_id),
+                ("DataCollection", C::param_value()),
+            ],
+        )?;
+        Ok(response.data)
+    }
+
+    pub fn get_string_current_sum_data(
+        &self,
+        device_id: &DeviceId,
+        period: TimePeriod,
+    ) -> Result<CurrentSumStringControlData, Error> {
+        let device_id = u8::from(device_id).to_string();
+        let response: CommonResponseBody<_> = self.make_request(
+            "GetStringRealtimeData.cgi",
+            [
+                ("Scope", "Device"),
+                ("DeviceId", &device_id),
+                ("DataCollection", "CurrentSumStringControlData"),
+                ("TimePeriod", period.param_value()),
+            ],
+        )?;
+        Ok(response.data)
+    }
+
     pub fn get_power_flow_realtime_data(&self) -> Result<PowerFlowData, Error> {
         let response: CommonResponseBody<_> =
             self.make_request("GetPowerFlowRealtimeData.fcgi", [] as [(&str, &str); 0])?;
         Ok(response.data)
     }
+
+    /// Reads the recorded history of the given channels from the Datamanager archive.
+    ///
+    /// The Datamanager rejects requests spanning more than 16 days.
+    pub fn get_archive_data(
+        &self,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+        channels: &[ArchiveChannel],
+        scope: &ArchiveScope,
+    ) -> Result<ArchiveData, Error> {
+        let params = archive_params(start, end, channels, scope)?;
+        let response: CommonResponseBody<_> = self.make_request("GetArchiveData.cgi", params)?;
+        Ok(response.data)
+    }
+
+    /// Requests every known endpoint and data collection once and reports which of them
+    /// the device supports.
+    ///
+    /// Device scoped data is requested from the first device of the matching type
+    /// reported by `GetActiveDeviceInfo.cgi`. Without such a device the capability is
+    /// reported as [`Support::NoDevice`].
+    pub fn probe_capabilities(&self) -> CapabilityReport {
+        let mut support = BTreeMap::new();
+
+        let mut devices: HashMap<DeviceType, DeviceId> = HashMap::new();
+        let device_info = self.get_active_device_info();
+        if let Ok(device_info) = &device_info {
+            for (device_type, infos) in device_info {
+                let first = infos
+                    .keys()
+                    .filter_map(|id| DeviceId::try_from(id.parse::<u8>().ok()?).ok())
+                    .min_by_key(|id| u8::from(id));
+                if let Some(first) = first {
+                    devices.insert(*device_type, first);
+                }
+            }
+        }
+        support.insert(Capability::ActiveDeviceInfo, Support::from_result(device_info.map(drop)));
+
+        for capability in Capability::ALL {
+            if capability == Capability::ActiveDeviceInfo {
+                continue;
+            }
+            let result = match capability.device_type() {
+                Some(device_type) => match devices.get(&device_type) {
+                    Some(device_id) => Support::from_result(self.probe(capability, Some(device_id))),
+                    None => Support::NoDevice,
+                },
+                None => Support::from_result(self.probe(capability, None)),
+            };
+            support.insert(capability, result);
+        }
+
+        CapabilityReport { support }
+    }
+
+    /// Requests the data of a single capability, `device_id` is given for device scoped
+    /// capabilities.
+    fn probe(&self, capability: Capability, device_id: Option<&DeviceId>) -> Result<(), Error> {
+        let device = || device_id.expect("Device scoped capabilities are probed with a device");
+        match capability {
+            Capability::InverterRealtimeDataSystem => self.get_inverter_realtime_data_system().map(drop),
+            Capability::CommonInverterData => self.get_inverter_realtime_data_device::<CommonInverterData>(device()).map(drop),
+            Capability::ThreePhaseInverterData => self.get_inverter_realtime_data_device::<ThreePhaseInverterData>(device()).map(drop),
+            Capability::MinMaxInverterData => self.get_inverter_realtime_data_device::<MinMaxInverterData>(device()).map(drop),
+            Capability::CumulationInverterData => self.get_inverter_realtime_data_device::<CumulationInverterData>(device()).map(drop),
+            Capability::InverterInfo => self.get_inverter_info().map(drop),
+            Capability::ActiveDeviceInfo => self.get_active_device_info().map(drop),
+            Capability::LoggerInfo => self.get_logger_info().map(drop),
+            Capability::LoggerLedInfo => self.get_logger_led_info().map(drop),
+            Capability::MeterRealtimeData => self.get_meter_realtime_data_device(device()).map(drop),
+            Capability::StorageRealtimeData => self.get_storage_realtime_data_device(device()).map(drop),
+            Capability::OhmPilotRealtimeData => self.get_ohm_pilot_realtime_data_device(device()).map(drop),
+            Capability::NowSensorData => self.get_sensor_realtime_data::<NowSensorData>(device()).map(drop),
+            Capability::MinMaxSensorData => self.get_sensor_realtime_data::<MinMaxSensorData>(device()).map(drop),
+            Capability::NowStringControlData => self.get_string_realtime_data::<NowStringControlData>(device()).map(drop),
+            Capability::LastErrorStringControlData => self.get_string_realtime_data::<LastErrorStringControlData>(device()).map(drop),
+            Capability::CurrentSumStringControlData => self.get_string_current_sum_data(device(), TimePeriod::Day).map(drop),
+            Capability::PowerFlowRealtimeData => self.get_power_flow_realtime_data().map(drop),
+            Capability::ArchiveData => {
+                let end = OffsetDateTime::now_utc();
+                let start = end - time::Duration::hours(1);
+                self.get_archive_data(start, end, &[ArchiveChannel::TimeSpanInSec], &ArchiveScope::System)
+                    .map(drop)
+            }
+        }
+    }
+}
+
+/// Async counterpart of [`Fronius`], built on the non-blocking `reqwest::Client`.
+///
+/// Every `get_*` method of [`Fronius`] is available here as an `async fn` returning the
+/// same response types, so many devices can be polled concurrently from one runtime.
+pub struct AsyncFronius {
+    client: reqwest::Client,
+    base_url: Url,
+    retry_policy: RetryPolicy,
+    timestamp: Option<TimestampRecorder>,
+}
+
+impl AsyncFronius {
+    pub async fn connect(ip: IpAddr) -> Result<Self, Error> {
+        FroniusBuilder::from_ip(ip).build_async().await
+    }
+
+    /// Connects to the device reachable at `url`, e.g. `http://127.0.0.1:8080/`.
+    pub async fn connect_url(url: Url) -> Result<Self, Error> {
+        FroniusBuilder::from_url(url).build_async().await
+    }
+
+    /// See [`Fronius::timestamped`], `request` receives a handle to the same device.
+    pub async fn timestamped<T, F>(&self, request: impl FnOnce(AsyncFronius) -> F) -> Result<Timestamped<T>, Error>
+    where
+        F: Future<Output = Result<T, Error>>,
+    {
+        let timestamp = TimestampRecorder::default();
+        let fronius = AsyncFronius {
+            client: self.client.clone(),
+            base_url: self.base_url.clone(),
+            retry_policy: self.retry_policy.clone(),
+            timestamp: Some(timestamp.clone()),
+        };
+        let data = request(fronius).await?;
+        let timestamp = *timestamp.lock().unwrap();
+        Ok(Timestamped { timestamp, data })
+    }
+
+    async fn make_request_inner(&self, url: Url) -> Result<serde_json::Value, Error> {
+        let response = self
+            .retry_policy
+            .retry_async(|| async {
+                let response: FroniusResponse<serde_json::Value> =
+                    self.client.get(url.clone()).send().await?.json().await?;
+                response.into_ok()
+            })
+            .await?;
+        if let Some(timestamp) = &self.timestamp {
+            *timestamp.lock().unwrap() = Some(response.head.timestamp);
+        }
+        Ok(response.body)
+    }
+
+    pub async fn make_request<T, I, K, V>(&self, endpoint: &str, params: I) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+        I: IntoIterator,
+        I::Item: Borrow<(K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let url = endpoint_url(&self.base_url, endpoint, params)?;
+        let body = self.make_request_inner(url).await?;
+
+        Ok(T::deserialize(body)?)
+    }
+
+    pub async fn get_inverter_realtime_data_device<C: DataCollection>(
+        &self,
+        device_id: &DeviceId,
+    ) -> Result<C, Error> {
+        let device_id = u8::from(device_id).to_string();
+
+        let response: CommonResponseBody<_> = self
+            .make_request(
+                "GetInverterRealtimeData.cgi",
+                [
+                    ("Scope", "Device"),
+                    ("DeviceId", &device_id),
+                    ("DataCollection", C::param_value()),
+                ],
+            )
+            .await?;
+
+        Ok(response.data)
+    }
+
+    pub async fn get_inverter_realtime_data_system(
+        &self,
+    ) -> Result<CumulationInverterDataSystem, Error> {
+        let response: CommonResponseBody<_> = self
+            .make_request("GetInverterRealtimeData.cgi", [("Scope", "System")])
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_inverter_info(&self) -> Result<InverterInfos, Error> {
+        let response: CommonResponseBody<_> = self
+            .make_request("GetInverterInfo.cgi", [] as [(&str, &str); 0])
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_active_device_info(&self) -> Result<DeviceInfos, Error> {
+        let response: CommonResponseBody<_> = self
+            .make_request("GetActiveDeviceInfo.cgi", [] as [(&str, &str); 0])
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_logger_info(&self) -> Result<LoggerInfo, Error> {
+        let response: LoggerInfoBody = self
+            .make_request("GetLoggerInfo.cgi", [] as [(&str, &str); 0])
+            .await?;
+        Ok(response.logger_info)
+    }
+
+    pub async fn get_logger_led_info(&self) -> Result<LoggerLedInfo, Error> {
+        let response: CommonResponseBody<_> = self
+            .make_request("GetLoggerLEDInfo.cgi", [] as [(&str, &str); 0])
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_meter_realtime_data_system(&self) -> Result<MeterDataSystem, Error> {
+        let response: CommonResponseBody<_> = self
+            .make_request("GetMeterRealtimeData.cgi", [("Scope", "System")])
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_meter_realtime_data_device(
+        &self,
+        device_id: &DeviceId,
+    ) -> Result<MeterData, Error> {
+        let device_id = u8::from(device_id).to_string();
+        let response: CommonResponseBody<_> = self
+            .make_request(
+                "GetMeterRealtimeData.cgi",
+                [("Scope", "Device"), ("DeviceId", &device_id)],
+            )
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_storage_realtime_data_system(&self) -> Result<StorageDataSystem, Error> {
+        let response: CommonResponseBody<_> = self
+            .make_request("GetStorageRealtimeData.cgi", [("Scope", "System")])
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_storage_realtime_data_device(
+        &self,
+        device_id: &DeviceId,
+    ) -> Result<StorageData, Error> {
+        let device_id = u8::from(device_id).to_string();
+        let response: CommonResponseBody<_> = self
+            .make_request(
+                "GetStorageRealtimeData.cgi",
+                [("Scope", "Device"), ("DeviceId", &device_id)],
+            )
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_ohm_pilot_realtime_data_system(&self) -> Result<OhmPilotDataSystem, Error> {
+        let response: CommonResponseBody<_> = self
+            .make_request("GetOhmPilotRealtimeData.cgi", [("Scope", "System")])
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_ohm_pilot_realtime_data_device(
+        &self,
+        device_id: &DeviceId,
+    ) -> Result<OhmPilotData, Error> {
+        let device_id = u8::from(device_id).to_string();
+        let response: CommonResponseBody<_> = self
+            .make_request(
+                "GetOhmPilotRealtimeData.cgi",
+                [("Scope", "Device"), ("DeviceId", &device_id)],
+            )
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_sensor_realtime_data<C: SensorDataCollection>(
+        &self,
+        device_id: &DeviceId,
+    ) -> Result<C, Error> {
+        let device_id = u8::from(device_id).to_string();
+        let response: CommonResponseBody<_> = self
+            .make_request(
+                "GetSensorRealtimeData.cgi",
+                [
+                    ("Scope", "Device"),
+                    ("DeviceId", &device_id),
+                    ("DataCollection", C::param_value()),
+                ],
+            )
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_string_realtime_data<C: StringControlDataCollection>(
+        &self,
+        device_id: &DeviceId,
+    ) -> Result<C, Error> {
+        let device_id = u8::from(device_id).to_string();
+        let response: CommonResponseBody<_> = self
+            .make_request(
+                "GetStringRealtimeData.cgi",
+                [
+                    ("Scope", "Device"),
+                    ("DeviceId", &device_id),
+                    ("DataCollection", C::param_value()),
+                ],
+            )
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_string_current_sum_data(
+        &self,
+        device_id: &DeviceId,
+        period: TimePeriod,
+    ) -> Result<CurrentSumStringControlData, Error> {
+        let device_id = u8::from(device_id).to_string();
+        let response: CommonResponseBody<_> = self
+            .make_request(
+                "GetStringRealtimeData.cgi",
+                [
+                    ("Scope", "Device"),
+                    ("DeviceId", &device_id),
+                    ("DataCollection", "CurrentSumStringControlData"),
+                    ("TimePeriod", period.param_value()),
+                ],
+            )
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_power_flow_realtime_data(&self) -> Result<PowerFlowData, Error> {
+        let response: CommonResponseBody<_> = self
+            .make_request("GetPowerFlowRealtimeData.fcgi", [] as [(&str, &str); 0])
+            .await?;
+        Ok(response.data)
+    }
+
+    pub async fn get_archive_data(
+        &self,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+        channels: &[ArchiveChannel],
+        scope: &ArchiveScope,
+    ) -> Result<ArchiveData, Error> {
+        let params = archive_params(start, end, channels, scope)?;
+        let response: CommonResponseBody<_> =
+            self.make_request("GetArchiveData.cgi", params).await?;
+        Ok(response.data)
+    }
+}
+
+/// Returns the URL of the device with the given IP.
+fn device_url(ip: IpAddr) -> Url {
+    let mut url = reqwest::Url::parse("http://placeholder.local/")
+        .expect("Initial base URL should be valid");
+    url.set_ip_host(ip)
+        .expect("Base URL should be a valid base");
+    url
+}
+
+/// Parses a URL, hostname or IP address, optionally followed by a port, into the URL of
+/// the device. Plain hosts are reached via HTTP.
+fn device_url_from_str(address: &str) -> Result<Url, Error> {
+    let address = address.trim();
+    if let Ok(ip) = address.parse::<IpAddr>() {
+        return Ok(device_url(ip));
+    }
+    let url = if address.contains("://") {
+        Url::parse(address)
+    } else {
+        Url::parse(&format!("http://{address}/"))
+    };
+    match url {
+        Ok(url) if url.has_host() && matches!(url.scheme(), "http" | "https") => Ok(url),
+        _ => Err(Error::InvalidAddress(address.to_string())),
+    }
+}
+
+/// Returns the URL of `GetAPIVersion.cgi` on the device reachable at `url`.
+fn api_version_url(url: &Url) -> Result<Url, Error> {
+    url.join("solar_api/GetAPIVersion.cgi")
+        .map_err(|_e| Error::InvalidEndpoint(url.to_string()))
+}
+
+/// Checks the reported API version and derives the base URL of all API endpoints from it.
+///
+/// The reported base URL is absolute, it is resolved relative to `url` to keep the path
+/// of devices behind a reverse proxy.
+fn api_base_url(url: &Url, api_version: ApiVersion) -> Result<Url, Error> {
+    if api_version.api_version != 1 {
+        return Err(Error::UnsupportedApiVersion(api_version.api_version));
+    }
+
+    url.join(api_version.base_url.trim_start_matches('/'))
+        .map_err(|_e| Error::InvalidEndpoint(api_version.base_url.to_string()))
+}
+
+fn endpoint_url<I, K, V>(base_url: &Url, endpoint: &str, params: I) -> Result<Url, Error>
+where
+    I: IntoIterator,
+    I::Item: Borrow<(K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut url = base_url
+        .join(endpoint)
+        .map_err(|_e| Error::InvalidEndpoint(endpoint.to_string()))?;
+    url.query_pairs_mut().extend_pairs(params);
+    Ok(url)
+}
+
+fn archive_params(
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    channels: &[ArchiveChannel],
+    scope: &ArchiveScope,
+) -> Result<Vec<(&'static str, String)>, Error> {
+    let format = |date: OffsetDateTime| {
+        date.format(&time::format_description::well_known::Rfc3339)
+            .map_err(|_e| Error::InvalidArchiveRange(start, end))
+    };
+    if start > end {
+        return Err(Error::InvalidArchiveRange(start, end));
+    }
+
+    let mut params = vec![
+        ("StartDate", format(start)?),
+        ("EndDate", format(end)?),
+    ];
+    match scope {
+        ArchiveScope::System => params.push(("Scope", "System".to_string())),
+        ArchiveScope::Device(device_type, device_id) => {
+            params.push(("Scope", "Device".to_string()));
+            params.push(("DeviceClass", device_type.param_value().to_string()));
+            params.push(("DeviceId", u8::from(device_id).to_string()));
+        }
+    }
+    params.extend(
+        channels
+            .iter()
+            .map(|channel| ("Channel", channel.param_value().to_string())),
+    );
+    Ok(params)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -175,6 +970,20 @@ pub struct FroniusResponse<T> {
     body: T,
 }
 
+impl<T> FroniusResponse<T> {
+    pub fn head(&self) -> &CommonResponseHeader {
+        &self.head
+    }
+
+    fn into_ok(self) -> Result<Self, Error> {
+        if self.head.status.code != StatusCode::Okay {
+            return Err(Error::Response(self.head.status));
+        }
+
+        Ok(self)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
 #[repr(u8)]
 pub enum StatusCode {
@@ -194,6 +1003,17 @@ pub enum StatusCode {
     UnknownError = 255,
 }
 
+impl StatusCode {
+    /// Classifies the status code of an error response.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            StatusCode::Timeout | StatusCode::LNRequestTimeout | StatusCode::DeviceNotAvailable => ErrorKind::Transient,
+            StatusCode::NotImplemented | StatusCode::NotSupported => ErrorKind::Unsupported,
+            _ => ErrorKind::Permanent,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Status {
@@ -202,6 +1022,177 @@ pub struct Status {
     user_message: String,
 }
 
+impl Status {
+    pub fn code(&self) -> StatusCode {
+        self.code
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// The message the Datamanager would show to the user, often empty.
+    pub fn user_message(&self) -> &str {
+        &self.user_message
+    }
+}
+
+/// An endpoint or data collection of the Solar API, as probed by
+/// [`Fronius::probe_capabilities`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Capability {
+    InverterRealtimeDataSystem,
+    CommonInverterData,
+    ThreePhaseInverterData,
+    MinMaxInverterData,
+    CumulationInverterData,
+    InverterInfo,
+    ActiveDeviceInfo,
+    LoggerInfo,
+    LoggerLedInfo,
+    MeterRealtimeData,
+    StorageRealtimeData,
+    OhmPilotRealtimeData,
+    NowSensorData,
+    MinMaxSensorData,
+    NowStringControlData,
+    LastErrorStringControlData,
+    CurrentSumStringControlData,
+    PowerFlowRealtimeData,
+    ArchiveData,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 19] = [
+        Capability::InverterRealtimeDataSystem,
+        Capability::CommonInverterData,
+        Capability::ThreePhaseInverterData,
+        Capability::MinMaxInverterData,
+        Capability::CumulationInverterData,
+        Capability::InverterInfo,
+        Capability::ActiveDeviceInfo,
+        Capability::LoggerInfo,
+        Capability::LoggerLedInfo,
+        Capability::MeterRealtimeData,
+        Capability::StorageRealtimeData,
+        Capability::OhmPilotRealtimeData,
+        Capability::NowSensorData,
+        Capability::MinMaxSensorData,
+        Capability::NowStringControlData,
+        Capability::LastErrorStringControlData,
+        Capability::CurrentSumStringControlData,
+        Capability::PowerFlowRealtimeData,
+        Capability::ArchiveData,
+    ];
+
+    /// The type of device the data is requested from, `None` for system-wide data.
+    pub fn device_type(&self) -> Option<DeviceType> {
+        match self {
+            Capability::CommonInverterData
+            | Capability::ThreePhaseInverterData
+            | Capability::MinMaxInverterData
+            | Capability::CumulationInverterData => Some(DeviceType::Inverter),
+            Capability::MeterRealtimeData => Some(DeviceType::Meter),
+            Capability::StorageRealtimeData => Some(DeviceType::Storage),
+            Capability::OhmPilotRealtimeData => Some(DeviceType::Ohmpilot),
+            Capability::NowSensorData | Capability::MinMaxSensorData => Some(DeviceType::SensorCard),
+            Capability::NowStringControlData
+            | Capability::LastErrorStringControlData
+            | Capability::CurrentSumStringControlData => Some(DeviceType::StringControl),
+            _ => None,
+        }
+    }
+
+    /// The endpoint and, if any, the data collection, e.g.
+    /// `GetInverterRealtimeData.cgi CommonInverterData`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::InverterRealtimeDataSystem => "GetInverterRealtimeData.cgi System",
+            Capability::CommonInverterData => "GetInverterRealtimeData.cgi CommonInverterData",
+            Capability::ThreePhaseInverterData => "GetInverterRealtimeData.cgi 3PInverterData",
+            Capability::MinMaxInverterData => "GetInverterRealtimeData.cgi MinMaxInverterData",
+            Capability::CumulationInverterData => "GetInverterRealtimeData.cgi CumulationInverterData",
+            Capability::InverterInfo => "GetInverterInfo.cgi",
+            Capability::ActiveDeviceInfo => "GetActiveDeviceInfo.cgi",
+            Capability::LoggerInfo => "GetLoggerInfo.cgi",
+            Capability::LoggerLedInfo => "GetLoggerLEDInfo.cgi",
+            Capability::MeterRealtimeData => "GetMeterRealtimeData.cgi",
+            Capability::StorageRealtimeData => "GetStorageRealtimeData.cgi",
+            Capability::OhmPilotRealtimeData => "GetOhmPilotRealtimeData.cgi",
+            Capability::NowSensorData => "GetSensorRealtimeData.cgi NowSensorData",
+            Capability::MinMaxSensorData => "GetSensorRealtimeData.cgi MinMaxSensorData",
+            Capability::NowStringControlData => "GetStringRealtimeData.cgi NowStringControlData",
+            Capability::LastErrorStringControlData => "GetStringRealtimeData.cgi LastErrorStringControlData",
+            Capability::CurrentSumStringControlData => "GetStringRealtimeData.cgi CurrentSumStringControlData",
+            Capability::PowerFlowRealtimeData => "GetPowerFlowRealtimeData.fcgi",
+            Capability::ArchiveData => "GetArchiveData.cgi",
+        }
+    }
+}
+
+/// Whether a device supports a [`Capability`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Support {
+    Supported,
+    /// The device answered with `NotImplemented` or `NotSupported`.
+    Unsupported,
+    /// No device of the type the data is requested from is connected.
+    NoDevice,
+    /// The request failed for another reason, so support is unknown.
+    Failed(ErrorKind),
+}
+
+impl Support {
+    fn from_result(result: Result<(), Error>) -> Self {
+        match result {
+            Ok(()) => Support::Supported,
+            Err(error) if error.is_unsupported() => Support::Unsupported,
+            Err(error) => Support::Failed(error.kind()),
+        }
+    }
+}
+
+impl std::fmt::Display for Support {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Support::Supported => write!(f, "supported"),
+            Support::Unsupported => write!(f, "not supported"),
+            Support::NoDevice => write!(f, "no device"),
+            Support::Failed(kind) => write!(f, "failed ({kind:?})"),
+        }
+    }
+}
+
+/// The result of [`Fronius::probe_capabilities`].
+#[derive(Debug, Clone)]
+pub struct CapabilityReport {
+    support: BTreeMap<Capability, Support>,
+}
+
+impl CapabilityReport {
+    pub fn support(&self, capability: Capability) -> Support {
+        self.support[&capability]
+    }
+
+    pub fn is_supported(&self, capability: Capability) -> bool {
+        self.support(capability) == Support::Supported
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Capability, Support)> + '_ {
+        self.support.iter().map(|(capability, support)| (*capability, *support))
+    }
+}
+
+/// Lists every capability on its own line.
+impl std::fmt::Display for CapabilityReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (capability, support) in self.iter() {
+            writeln!(f, "  {:<53} {support}", capability.name())?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct CommonResponseHeader {
@@ -211,6 +1202,21 @@ pub struct CommonResponseHeader {
     timestamp: OffsetDateTime,
 }
 
+impl CommonResponseHeader {
+    pub fn request_arguments(&self) -> &HashMap<String, String> {
+        &self.request_arguments
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// When the device answered the request.
+    pub fn timestamp(&self) -> OffsetDateTime {
+        self.timestamp
+    }
+}
+
 type DeviceStatus = Option<HashMap<String, serde_json::Value>>;
 
 mod inner {
@@ -259,6 +1265,7 @@ struct ApiVersion {
     compatibility_range: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct DeviceId(u8);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
@@ -334,6 +1341,20 @@ pub struct CommonInverterData {
     pub device_status: DeviceStatus,
 }
 
+impl CommonInverterData {
+    /// Returns the DC current and voltage of every MPP tracker as `(tracker, current,
+    /// voltage)`, ordered by tracker number. Trackers the inverter does not have report
+    /// neither value.
+    pub fn mppt_values(&self) -> [(u8, Option<f64>, Option<f64>); 4] {
+        [
+            (1, self.idc.value, self.udc.value),
+            (2, self.idc_2.value, self.udc_2.value),
+            (3, self.idc_3.value, self.udc_3.value),
+            (4, self.idc_4.value, self.udc_4.value),
+        ]
+    }
+}
+
 pub type ThreePInverterData = ThreePhaseInverterData;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -352,6 +1373,23 @@ pub struct ThreePhaseInverterData {
     pub rotation_speed_fan_br: Option<UnitAndValue<f64>>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub struct MinMaxInverterData {
+    pub day_pmax: UnitAndValue<f64>,
+    pub day_uacmax: UnitAndValue<f64>,
+    pub day_uacmin: UnitAndValue<f64>,
+    pub day_udcmax: UnitAndValue<f64>,
+    pub year_pmax: UnitAndValue<f64>,
+    pub year_uacmax: UnitAndValue<f64>,
+    pub year_uacmin: UnitAndValue<f64>,
+    pub year_udcmax: UnitAndValue<f64>,
+    pub total_pmax: UnitAndValue<f64>,
+    pub total_uacmax: UnitAndValue<f64>,
+    pub total_uacmin: UnitAndValue<f64>,
+    pub total_udcmax: UnitAndValue<f64>,
+}
+
 impl DataCollection for CumulationInverterData {
     fn param_value() -> &'static str {
         "CumulationInverterData"
@@ -370,6 +1408,12 @@ impl DataCollection for ThreePhaseInverterData {
     }
 }
 
+impl DataCollection for MinMaxInverterData {
+    fn param_value() -> &'static str {
+        "MinMaxInverterData"
+    }
+}
+
 pub type InverterInfos = HashMap<String, Option<InverterInfo>>;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -429,14 +1473,82 @@ pub enum DeviceType {
     System,
 }
 
+impl DeviceType {
+    /// Returns the value of the `DeviceClass` GET parameter for this device type.
+    pub fn param_value(&self) -> &'static str {
+        match self {
+            DeviceType::Inverter => "Inverter",
+            DeviceType::Storage => "Storage",
+            DeviceType::Ohmpilot => "Ohmpilot",
+            DeviceType::SensorCard => "SensorCard",
+            DeviceType::StringControl => "StringControl",
+            DeviceType::Meter => "Meter",
+            DeviceType::System => "System",
+        }
+    }
+}
+
 pub type DeviceInfos = HashMap<DeviceType, HashMap<String, Option<DeviceInfo>>>;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct DeviceInfo {
     #[serde(rename = "DT")]
-    dt: i64,
-    serial: String,
+    pub dt: i64,
+    pub serial: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LoggerInfoBody {
+    logger_info: LoggerInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LoggerInfo {
+    #[serde(rename = "CO2Factor")]
+    pub co2_factor: Option<f64>,
+    #[serde(rename = "CO2Unit")]
+    pub co2_unit: Option<String>,
+    pub cash_currency: Option<String>,
+    pub cash_factor: Option<f64>,
+    pub default_language: Option<String>,
+    pub delivery_factor: Option<f64>,
+    #[serde(rename = "HWVersion")]
+    pub hw_version: Option<String>,
+    #[serde(rename = "PlatformID")]
+    pub platform_id: Option<String>,
+    #[serde(rename = "ProductID")]
+    pub product_id: Option<String>,
+    #[serde(rename = "SWVersion")]
+    pub sw_version: Option<String>,
+    pub timezone_location: Option<String>,
+    pub timezone_name: Option<String>,
+    #[serde(rename = "UTCOffset")]
+    pub utc_offset: Option<i64>,
+    #[serde(rename = "UniqueID")]
+    pub unique_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LoggerLedInfo {
+    #[serde(rename = "PowerLED")]
+    pub power_led: Option<LedState>,
+    #[serde(rename = "SolarNetLED")]
+    pub solar_net_led: Option<LedState>,
+    #[serde(rename = "SolarWebLED")]
+    pub solar_web_led: Option<LedState>,
+    #[serde(rename = "WLANLED")]
+    pub wlan_led: Option<LedState>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LedState {
+    pub color: String,
+    pub state: String,
 }
 
 pub type MeterDataSystem = HashMap<String, MeterData>;
@@ -540,9 +1652,9 @@ pub struct MeterData {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct DeviceDetails {
-    manufacturer: String,
-    model: String,
-    serial: String,
+    pub manufacturer: String,
+    pub model: String,
+    pub serial: String,
 }
 
 pub type StorageDataSystem = HashMap<String, StorageData>;
@@ -571,6 +1683,8 @@ pub struct StorageController {
     pub voltage_dc: f64,
     #[serde(rename = "Temperature_Cell")]
     pub temperature_cell: f64,
+    #[serde(rename = "DesignedCapacity")]
+    pub designed_capacity: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -606,6 +1720,179 @@ pub struct StorageModule {
     pub voltage_dc_minimum_cell: Option<f64>,
 }
 
+pub trait SensorDataCollection: DeserializeOwned {
+    /// Returns the value of the `DataCollection` GET parameter for this collection.
+    fn param_value() -> &'static str;
+}
+
+/// Channels of a Sensor Card / Sensor Box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SensorChannel {
+    /// Temperature 1, usually the module temperature sensor.
+    ModuleTemperature = 0,
+    /// Temperature 2, usually the ambient temperature sensor.
+    AmbientTemperature = 1,
+    Irradiance = 2,
+    /// Digital input 1, usually the wind speed sensor.
+    WindSpeed = 3,
+    Digital2 = 4,
+    CurrentLoop = 5,
+}
+
+impl SensorChannel {
+    fn key(&self) -> String {
+        (*self as u8).to_string()
+    }
+}
+
+/// Current sensor values keyed by channel number.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NowSensorData {
+    pub channels: HashMap<String, UnitAndValue<f64>>,
+}
+
+impl NowSensorData {
+    pub fn channel(&self, channel: SensorChannel) -> Option<&UnitAndValue<f64>> {
+        self.channels.get(&channel.key())
+    }
+
+    /// Returns the value of a channel, if the sensor reports it.
+    pub fn value(&self, channel: SensorChannel) -> Option<f64> {
+        self.channel(channel).and_then(|channel| channel.value)
+    }
+}
+
+/// Minimum and maximum sensor values keyed by channel number.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MinMaxSensorData {
+    pub channels: HashMap<String, SensorMinMax>,
+}
+
+impl MinMaxSensorData {
+    pub fn channel(&self, channel: SensorChannel) -> Option<&SensorMinMax> {
+        self.channels.get(&channel.key())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SensorMinMax {
+    pub unit: String,
+    pub day: Option<MinMax>,
+    pub month: Option<MinMax>,
+    pub year: Option<MinMax>,
+    pub total: Option<MinMax>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MinMax {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl SensorDataCollection for NowSensorData {
+    fn param_value() -> &'static str {
+        "NowSensorData"
+    }
+}
+
+impl SensorDataCollection for MinMaxSensorData {
+    fn param_value() -> &'static str {
+        "MinMaxSensorData"
+    }
+}
+
+pub trait StringControlDataCollection: DeserializeOwned {
+    /// Returns the value of the `DataCollection` GET parameter for this collection.
+    fn param_value() -> &'static str;
+}
+
+/// Current per string in A, keyed by string number.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NowStringControlData {
+    pub strings: HashMap<String, UnitAndValue<f64>>,
+}
+
+impl NowStringControlData {
+    /// Returns the current of every string, ordered by string number.
+    pub fn values(&self) -> Vec<(u8, Option<f64>)> {
+        string_values(&self.strings)
+    }
+}
+
+/// Accumulated current per string over a [`TimePeriod`] in Ah, keyed by string number.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CurrentSumStringControlData {
+    pub strings: HashMap<String, UnitAndValue<f64>>,
+}
+
+impl CurrentSumStringControlData {
+    /// Returns the accumulated current of every string, ordered by string number.
+    pub fn values(&self) -> Vec<(u8, Option<f64>)> {
+        string_values(&self.strings)
+    }
+}
+
+fn string_values(strings: &HashMap<String, UnitAndValue<f64>>) -> Vec<(u8, Option<f64>)> {
+    let mut values: Vec<_> = strings
+        .iter()
+        .filter_map(|(string, value)| Some((string.parse().ok()?, value.value)))
+        .collect();
+    values.sort_by_key(|(string, _)| *string);
+    values
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LastErrorStringControlData {
+    pub time_of_error: Option<String>,
+    pub string_average: Option<UnitAndValue<f64>>,
+    #[serde(default)]
+    pub strings: HashMap<String, StringControlError>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StringControlError {
+    pub current: Option<UnitAndValue<f64>>,
+    pub relative_deviation: Option<UnitAndValue<f64>>,
+}
+
+impl StringControlDataCollection for NowStringControlData {
+    fn param_value() -> &'static str {
+        "NowStringControlData"
+    }
+}
+
+impl StringControlDataCollection for LastErrorStringControlData {
+    fn param_value() -> &'static str {
+        "LastErrorStringControlData"
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimePeriod {
+    Day,
+    Year,
+    Total,
+}
+
+impl TimePeriod {
+    /// Returns the value of the `TimePeriod` GET parameter for this period.
+    pub fn param_value(&self) -> &'static str {
+        match self {
+            TimePeriod::Day => "Day",
+            TimePeriod::Year => "Year",
+            TimePeriod::Total => "Total",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct OhmPilotDetails {
@@ -731,3 +2018,160 @@ pub struct PowerFlowSecondaryMeters {
     pub label: String,
     pub category: String,
 }
+
+/// Selects whose history is read by [`Fronius::get_archive_data`].
+#[derive(Debug)]
+pub enum ArchiveScope {
+    System,
+    Device(DeviceType, DeviceId),
+}
+
+/// Channels recorded by the Datamanager archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArchiveChannel {
+    TimeSpanInSec,
+    EnergyRealWacSumProduced,
+    EnergyRealWacSumConsumed,
+    EnergyRealWacPlusAbsolute,
+    EnergyRealWacMinusAbsolute,
+    PowerRealPacSum,
+    CurrentDcString1,
+    CurrentDcString2,
+    VoltageDcString1,
+    VoltageDcString2,
+    CurrentAcPhase1,
+    CurrentAcPhase2,
+    CurrentAcPhase3,
+    VoltageAcPhase1,
+    VoltageAcPhase2,
+    VoltageAcPhase3,
+    TemperaturePowerstage,
+    MeterLocationCurrent,
+    StateOfChargeRelative,
+    CurrentDc,
+    VoltageDc,
+    TemperatureCell,
+    TemperatureChannel1,
+    TemperatureChannel2,
+    DigitalChannel1,
+    DigitalChannel2,
+    Radiation,
+}
+
+impl ArchiveChannel {
+    /// Returns the value of the `Channel` GET parameter for this channel.
+    pub fn param_value(&self) -> &'static str {
+        match self {
+            ArchiveChannel::TimeSpanInSec => "TimeSpanInSec",
+            ArchiveChannel::EnergyRealWacSumProduced => "EnergyReal_WAC_Sum_Produced",
+            ArchiveChannel::EnergyRealWacSumConsumed => "EnergyReal_WAC_Sum_Consumed",
+            ArchiveChannel::EnergyRealWacPlusAbsolute => "EnergyReal_WAC_Plus_Absolute",
+            ArchiveChannel::EnergyRealWacMinusAbsolute => "EnergyReal_WAC_Minus_Absolute",
+            ArchiveChannel::PowerRealPacSum => "PowerReal_PAC_Sum",
+            ArchiveChannel::CurrentDcString1 => "Current_DC_String_1",
+            ArchiveChannel::CurrentDcString2 => "Current_DC_String_2",
+            ArchiveChannel::VoltageDcString1 => "Voltage_DC_String_1",
+            ArchiveChannel::VoltageDcString2 => "Voltage_DC_String_2",
+            ArchiveChannel::CurrentAcPhase1 => "Current_AC_Phase_1",
+            ArchiveChannel::CurrentAcPhase2 => "Current_AC_Phase_2",
+            ArchiveChannel::CurrentAcPhase3 => "Current_AC_Phase_3",
+            ArchiveChannel::VoltageAcPhase1 => "Voltage_AC_Phase_1",
+            ArchiveChannel::VoltageAcPhase2 => "Voltage_AC_Phase_2",
+            ArchiveChannel::VoltageAcPhase3 => "Voltage_AC_Phase_3",
+            ArchiveChannel::TemperaturePowerstage => "Temperature_Powerstage",
+            ArchiveChannel::MeterLocationCurrent => "Meter_Location_Current",
+            ArchiveChannel::StateOfChargeRelative => "StateOfCharge_Relative",
+            ArchiveChannel::CurrentDc => "Current_DC",
+            ArchiveChannel::VoltageDc => "Voltage_DC",
+            ArchiveChannel::TemperatureCell => "Temperature_Cell",
+            ArchiveChannel::TemperatureChannel1 => "Temperature_Channel_1",
+            ArchiveChannel::TemperatureChannel2 => "Temperature_Channel_2",
+            ArchiveChannel::DigitalChannel1 => "Digital_Channel_1",
+            ArchiveChannel::DigitalChannel2 => "Digital_Channel_2",
+            ArchiveChannel::Radiation => "Radiation",
+        }
+    }
+}
+
+/// Archive data keyed by the node name reported by the Datamanager (e.g. `inverter/1`).
+pub type ArchiveData = HashMap<String, ArchiveDeviceData>;
+
+#[derive(Debug, Deserialize)]
+#[serde(from = "ArchiveDeviceDataRaw")]
+pub struct ArchiveDeviceData {
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+    pub device_type: Option<i64>,
+    pub node_type: Option<i64>,
+    pub channels: HashMap<String, ArchiveSeries>,
+}
+
+impl ArchiveDeviceData {
+    pub fn channel(&self, channel: ArchiveChannel) -> Option<&ArchiveSeries> {
+        self.channels.get(channel.param_value())
+    }
+}
+
+/// Samples of one archive channel, sorted by time.
+#[derive(Debug)]
+pub struct ArchiveSeries {
+    pub unit: Option<String>,
+    pub values: Vec<(OffsetDateTime, f64)>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ArchiveDeviceDataRaw {
+    #[serde(with = "time::serde::rfc3339")]
+    start: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    end: OffsetDateTime,
+    device_type: Option<i64>,
+    node_type: Option<i64>,
+    #[serde(default)]
+    data: HashMap<String, ArchiveSeriesRaw>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ArchiveSeriesRaw {
+    unit: Option<String>,
+    /// Values keyed by their offset in seconds from the start of the archive range.
+    values: HashMap<String, Option<f64>>,
+}
+
+impl From<ArchiveDeviceDataRaw> for ArchiveDeviceData {
+    fn from(raw: ArchiveDeviceDataRaw) -> Self {
+        let start = raw.start;
+        let channels = raw
+            .data
+            .into_iter()
+            .map(|(name, series)| {
+                let mut values: Vec<_> = series
+                    .values
+                    .into_iter()
+                    .filter_map(|(offset, value)| {
+                        let offset = offset.parse::<i64>().ok()?;
+                        Some((start + time::Duration::seconds(offset), value?))
+                    })
+                    .collect();
+                values.sort_by_key(|(time, _)| *time);
+                (
+                    name,
+                    ArchiveSeries {
+                        unit: series.unit,
+                        values,
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            start,
+            end: raw.end,
+            device_type: raw.device_type,
+            node_type: raw.node_type,
+            channels,
+        }
+    }
+}
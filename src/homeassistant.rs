@@ -0,0 +1,284 @@
+use fronius_api::fronius::{self, DeviceId, DeviceType, Fronius};
+
+use crate::{
+    inventory::Inventory,
+    mqtt::{PayloadFormat, Publisher, ToMqtt},
+    InverterData, InverterSystemData, MeterData, OhmPilotData, PowerFlowData, StorageData, StorageHealthData,
+};
+
+/// A single value announced to Home Assistant as sensor.
+struct Entity {
+    field: &'static str,
+    name: &'static str,
+    device_class: Option<&'static str>,
+    state_class: &'static str,
+    unit: Option<String>,
+}
+
+impl Entity {
+    fn measurement(field: &'static str, name: &'static str, device_class: Option<&'static str>, unit: &str) -> Self {
+        Self {
+            field,
+            name,
+            device_class,
+            state_class: "measurement",
+            unit: unit_of_measurement(unit),
+        }
+    }
+
+    /// An energy counter, Home Assistant treats a decrease (e.g. of the day energy at
+    /// midnight) as reset.
+    fn energy(field: &'static str, name: &'static str, unit: &str) -> Self {
+        Self {
+            field,
+            name,
+            device_class: Some("energy"),
+            state_class: "total_increasing",
+            unit: unit_of_measurement(unit),
+        }
+    }
+}
+
+/// A device as shown in Home Assistant.
+struct Device {
+    identifier: String,
+    name: String,
+    model: Option<String>,
+    serial: Option<String>,
+}
+
+/// The Solar API reports some units differently than Home Assistant expects them.
+fn unit_of_measurement(unit: &str) -> Option<String> {
+    match unit {
+        "" => None,
+        "C" | "degC" => Some("°C".to_owned()),
+        unit => Some(unit.to_owned()),
+    }
+}
+
+/// Home Assistant only accepts `[a-zA-Z0-9_-]` in node and object IDs.
+fn object_id(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Publishes the discovery config of every entity and returns the number of configs that
+/// could not be published. A failed config does not keep the others from being published.
+fn announce_entities<T: ToMqtt>(publisher: &Publisher, discovery_prefix: &str, system: Option<&str>, device: &Device, device_id: Option<&DeviceId>, entities: &[Entity]) -> usize {
+    let node_id = object_id(&device.identifier);
+    let mut failed = 0;
+    for entity in entities {
+        let mut config = serde_json::json!({
+            "name": entity.name,
+            "unique_id": format!("{node_id}_{}", entity.field),
+            "state_class": entity.state_class,
+            "availability_topic": publisher.availability_topic(),
+            "device": {
+                "identifiers": [device.identifier],
+                "name": device.name,
+                "manufacturer": "Fronius",
+            },
+        });
+        match publisher.format() {
+            PayloadFormat::Json => {
+                config["state_topic"] = publisher.topic(system, T::TOPIC, device_id, None).into();
+                config["value_template"] = format!("{{{{ value_json.{} }}}}", entity.field).into();
+            }
+            PayloadFormat::Values => {
+                config["state_topic"] = publisher.topic(system, T::TOPIC, device_id, Some(entity.field)).into();
+            }
+        }
+        if let Some(device_class) = entity.device_class {
+            config["device_class"] = device_class.into();
+        }
+        if let Some(unit) = &entity.unit {
+            config["unit_of_measurement"] = unit.as_str().into();
+        }
+        if let Some(model) = &device.model {
+            config["device"]["model"] = model.as_str().into();
+        }
+        if let Some(serial) = &device.serial {
+            config["device"]["serial_number"] = serial.as_str().into();
+        }
+
+        let topic = format!("{discovery_prefix}/sensor/{node_id}/{}/config", entity.field);
+        let published = serde_json::to_vec(&config)
+            .map_err(|error| error.into())
+            .and_then(|payload| publisher.publish_raw(topic.to_owned(), payload));
+        if let Err(error) = published {
+            println!("Error during announcement of {topic} occured: {:?}", error);
+            failed += 1;
+        }
+    }
+    failed
+}
+
+fn inverter_entities(response: &fronius::CommonInverterData) -> Vec<Entity> {
+    let mut entities = vec![
+        Entity::measurement("ac_power", "AC power", Some("power"), &response.pac.unit),
+        Entity::measurement("ac_power_abs", "AC apparent power", Some("apparent_power"), &response.sac.unit),
+        Entity::measurement("ac_current", "AC current", Some("current"), &response.iac.unit),
+        Entity::measurement("ac_voltage", "AC voltage", Some("voltage"), &response.uac.unit),
+        Entity::measurement("dc_current", "DC current", Some("current"), &response.idc.unit),
+        Entity::measurement("dc_voltage", "DC voltage", Some("voltage"), &response.udc.unit),
+        Entity::energy("total_energy", "Total energy", &response.total_energy.unit),
+    ];
+    if let Some(fac) = &response.fac {
+        entities.push(Entity::measurement("ac_frequency", "AC frequency", Some("frequency"), &fac.unit));
+    }
+    entities
+}
+
+fn system_entities(response: &fronius::CumulationInverterDataSystem) -> Vec<Entity> {
+    vec![
+        Entity::measurement("ac_power", "AC power", Some("power"), &response.pac.unit),
+        Entity::energy("day_energy", "Energy today", &response.day_energy.unit),
+        Entity::energy("year_energy", "Energy this year", &response.year_energy.unit),
+        Entity::energy("total_energy", "Total energy", &response.total_energy.unit),
+    ]
+}
+
+fn meter_entities() -> Vec<Entity> {
+    vec![
+        Entity::measurement("power", "Power", Some("power"), "W"),
+        Entity::measurement("l1_power", "Power L1", Some("power"), "W"),
+        Entity::measurement("l2_power", "Power L2", Some("power"), "W"),
+        Entity::measurement("l3_power", "Power L3", Some("power"), "W"),
+        Entity::measurement("l1_current", "Current L1", Some("current"), "A"),
+        Entity::measurement("l2_current", "Current L2", Some("current"), "A"),
+        Entity::measurement("l3_current", "Current L3", Some("current"), "A"),
+        Entity::measurement("l1_voltage", "Voltage L1", Some("voltage"), "V"),
+        Entity::measurement("l2_voltage", "Voltage L2", Some("voltage"), "V"),
+        Entity::measurement("l3_voltage", "Voltage L3", Some("voltage"), "V"),
+        Entity::measurement("frequency_average", "Frequency", Some("frequency"), "Hz"),
+        Entity::measurement("apparent_power", "Apparent power", Some("apparent_power"), "VA"),
+        Entity::measurement("reactive_power", "Reactive power", Some("reactive_power"), "var"),
+        Entity::measurement("power_factor", "Power factor", Some("power_factor"), ""),
+        Entity::energy("energy_consumed", "Energy consumed", "Wh"),
+        Entity::energy("energy_produced", "Energy produced", "Wh"),
+    ]
+}
+
+fn storage_entities() -> Vec<Entity> {
+    vec![
+        Entity::measurement("charge_percentage", "State of charge", Some("battery"), "%"),
+        Entity::measurement("capacity", "Capacity", Some("energy_storage"), "Wh"),
+        Entity::measurement("dc_current", "DC current", Some("current"), "A"),
+        Entity::measurement("dc_voltage", "DC voltage", Some("voltage"), "V"),
+        Entity::measurement("temperature_cell", "Cell temperature", Some("temperature"), "°C"),
+    ]
+}
+
+fn storage_health_entities() -> Vec<Entity> {
+    vec![
+        Entity::measurement("state_of_health", "State of health", None, "%"),
+        Entity::measurement("cycle_count", "Cycle count", None, ""),
+        Entity::energy("charged_energy", "Energy charged", "Wh"),
+        Entity::energy("discharged_energy", "Energy discharged", "Wh"),
+        Entity::measurement("day_round_trip_efficiency", "Round-trip efficiency today", None, "%"),
+    ]
+}
+
+fn ohm_pilot_entities() -> Vec<Entity> {
+    vec![
+        Entity::measurement("power", "Power", Some("power"), "W"),
+        Entity::measurement("temperature", "Temperature", Some("temperature"), "°C"),
+    ]
+}
+
+fn power_flow_entities() -> Vec<Entity> {
+    vec![
+        Entity::measurement("akku", "Battery power", Some("power"), "W"),
+        Entity::measurement("grid", "Grid power", Some("power"), "W"),
+        Entity::measurement("load", "Load power", Some("power"), "W"),
+        Entity::measurement("photovoltaik", "PV power", Some("power"), "W"),
+        Entity::measurement("relative_autonomy", "Autonomy", None, "%"),
+        Entity::measurement("relative_self_consumption", "Self consumption", None, "%"),
+    ]
+}
+
+/// Describes a device, identified by its serial if the Datamanager reports one.
+fn device(inventory: &Inventory, logger_id: &str, device_type: DeviceType, device_id: &DeviceId, name: &str) -> Device {
+    let serial = inventory.serial(device_type, device_id).filter(|serial| !serial.is_empty());
+    Device {
+        identifier: serial.map_or(
+            format!("fronius_{logger_id}_{}_{}", device_type.param_value(), u8::from(device_id)),
+            |serial| format!("fronius_{serial}"),
+        ),
+        name: format!("Fronius {name} {}", u8::from(device_id)),
+        model: None,
+        serial: serial.map(str::to_owned),
+    }
+}
+
+/// Publishes Home Assistant discovery configs for every value of every discovered
+/// device of a system, if `homeassistant` is enabled in the MQTT config.
+///
+/// Devices are identified by their serial. If the Datamanager reports none, the name of
+/// the system is used instead, so several systems don't merge into one device.
+pub fn announce(system: Option<&str>, fronius: &Fronius, inventory: &Inventory, publisher: &Publisher) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(discovery_prefix) = publisher.homeassistant_prefix() else {
+        return Ok(());
+    };
+    let logger = fronius.get_logger_info().ok();
+    let logger_id = logger
+        .as_ref()
+        .and_then(|logger| logger.unique_id.clone())
+        .unwrap_or_else(|| object_id(system.unwrap_or("logger")));
+    let mut failed = 0;
+
+    let system_device = Device {
+        identifier: format!("fronius_{logger_id}"),
+        name: format!("Fronius {}", system.unwrap_or("System")),
+        model: logger.as_ref().and_then(|logger| logger.product_id.clone()),
+        serial: logger.as_ref().and_then(|logger| logger.unique_id.clone()),
+    };
+    match fronius.get_inverter_realtime_data_system() {
+        Ok(response) => failed += announce_entities::<InverterSystemData>(publisher, discovery_prefix, system, &system_device, None, &system_entities(&response)),
+        Err(error) => println!("Error during fetch of inverter_system_data occured: {:?}", error),
+    }
+    failed += announce_entities::<PowerFlowData>(publisher, discovery_prefix, system, &system_device, None, &power_flow_entities());
+
+    let infos = fronius.get_inverter_info().ok();
+    for inverter_id in inventory.devices(DeviceType::Inverter) {
+        let response = match fronius.get_inverter_realtime_data_device::<fronius::CommonInverterData>(inverter_id) {
+            Ok(response) => response,
+            Err(error) => {
+                println!("Error during fetch of inverter_data occured: {:?}", error);
+                continue;
+            }
+        };
+        let info = infos
+            .as_ref()
+            .and_then(|infos| infos.get(&u8::from(inverter_id).to_string()))
+            .and_then(Option::as_ref);
+        let mut inverter = device(inventory, &logger_id, DeviceType::Inverter, inverter_id, "Inverter");
+        if let Some(info) = info.filter(|info| !info.custom_name.is_empty()) {
+            inverter.name = info.custom_name.to_owned();
+        }
+        failed += announce_entities::<InverterData>(publisher, discovery_prefix, system, &inverter, Some(inverter_id), &inverter_entities(&response));
+    }
+
+    for meter_id in inventory.devices(DeviceType::Meter) {
+        let meter = device(inventory, &logger_id, DeviceType::Meter, meter_id, "Meter");
+        failed += announce_entities::<MeterData>(publisher, discovery_prefix, system, &meter, Some(meter_id), &meter_entities());
+    }
+
+    for storage_id in inventory.devices(DeviceType::Storage) {
+        let storage = device(inventory, &logger_id, DeviceType::Storage, storage_id, "Storage");
+        failed += announce_entities::<StorageData>(publisher, discovery_prefix, system, &storage, Some(storage_id), &storage_entities());
+        failed += announce_entities::<StorageHealthData>(publisher, discovery_prefix, system, &storage, Some(storage_id), &storage_health_entities());
+    }
+
+    for ohm_pilot_id in inventory.devices(DeviceType::Ohmpilot) {
+        let ohm_pilot = device(inventory, &logger_id, DeviceType::Ohmpilot, ohm_pilot_id, "Ohmpilot");
+        failed += announce_entities::<OhmPilotData>(publisher, discovery_prefix, system, &ohm_pilot, Some(ohm_pilot_id), &ohm_pilot_entities());
+    }
+
+    if failed > 0 {
+        return Err(format!("{failed} discovery configs could not be published").into());
+    }
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use influxdb2::{models::WriteDataPoint, writable::KeyWritable, Client};
+use serde::Serialize;
+
+use crate::{
+    config::InfluxConfig,
+    sink::{Sink, Snapshot},
+};
+
+/// Escapes a value for use as tag key or value in the InfluxDB line protocol.
+pub fn tag_value(value: &str) -> String {
+    if value.is_empty() {
+        return "unknown".to_owned();
+    }
+    value.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+/// Quotes a value as Flux string literal, e.g. to filter by a tag in a query.
+pub fn flux_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace("${", "\\${"))
+}
+
+/// Renders a Flux `filter()` for every tag, to select the points of one system or device.
+pub fn flux_tag_filters(tags: &[(String, String)]) -> String {
+    tags.iter()
+        .map(|(key, value)| format!("\n            |> filter(fn: (r) => r[{}] == {})", flux_string(key), flux_string(value)))
+        .collect()
+}
+
+/// A tag value read from the device, e.g. a serial or a custom name.
+///
+/// The value is kept as is for MQTT, Prometheus and Home Assistant and only escaped when
+/// the point is written in line protocol.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct TagValue(pub String);
+
+impl TagValue {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for TagValue {
+    fn from(value: &str) -> Self {
+        Self(value.to_owned())
+    }
+}
+
+impl KeyWritable for TagValue {
+    fn encode_key(&self) -> String {
+        tag_value(&self.0)
+    }
+}
+
+/// Adds `tags` to a single point in line protocol.
+///
+/// Every point written by the exporter has at least one tag, so the additional tags are
+/// inserted in front of the first one.
+fn with_tags(line: Vec<u8>, tags: &[(String, String)]) -> Vec<u8> {
+    let Some(first_tag) = line.iter().position(|byte| *byte == b',') else {
+        return line;
+    };
+    let mut tagged = line[..first_tag].to_vec();
+    for (key, value) in tags {
+        tagged.extend_from_slice(format!(",{}={}", tag_value(key), tag_value(value)).as_bytes());
+    }
+    tagged.extend_from_slice(&line[first_tag..]);
+    tagged
+}
+
+/// Writes every snapshot to InfluxDB in a single request.
+pub struct InfluxSink {
+    client: Client,
+    org: String,
+    bucket: String,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl InfluxSink {
+    pub fn new(config: &InfluxConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            client: Client::new(&config.url, &config.org, &config.token),
+            org: config.org.to_owned(),
+            bucket: config.bucket.to_owned(),
+            runtime: tokio::runtime::Builder::new_multi_thread().enable_all().build()?,
+        })
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    pub fn runtime(&self) -> &tokio::runtime::Runtime {
+        &self.runtime
+    }
+
+    /// Writes points given in line protocol with the additional `tags` in a single request.
+    pub fn write_lines(&self, lines: Vec<Vec<u8>>, tags: &[(String, String)]) -> Result<(), Box<dyn std::error::Error>> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let body: Vec<u8> = lines.into_iter().flat_map(|line| with_tags(line, tags)).collect();
+        self.runtime
+            .block_on(self.client.write_line_protocol(&self.org, &self.bucket, body))?;
+        Ok(())
+    }
+}
+
+/// Renders a point in line protocol.
+pub fn line(point: &impl WriteDataPoint) -> std::io::Result<Vec<u8>> {
+    let mut line = Vec::new();
+    point.write_data_point_to(&mut line)?;
+    Ok(line)
+}
+
+impl Sink for InfluxSink {
+    fn name(&self) -> &'static str {
+        "influxdb"
+    }
+
+    fn write(&mut self, snapshot: &Snapshot) -> Result<(), Box<dyn std::error::Error>> {
+        let mut lines = Vec::new();
+        for record in &snapshot.records {
+            let mut line = Vec::new();
+            record.point.write_line_protocol(&mut line)?;
+            lines.push(line);
+        }
+        self.write_lines(lines, &snapshot.tags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[(&str, &str)]) -> Vec<(String, String)> {
+        tags.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn with_tags_inserts_tags_in_front_of_first_tag() {
+        let line = b"meter,device=Meter,device_id=0 power=230.5 1714521600000000000\n".to_vec();
+        let tagged = with_tags(line, &tags(&[("system", "home"), ("location", "roof")]));
+        assert_eq!(
+            String::from_utf8(tagged).unwrap(),
+            "meter,system=home,location=roof,device=Meter,device_id=0 power=230.5 1714521600000000000\n"
+        );
+    }
+
+    #[test]
+    fn with_tags_escapes_tags() {
+        let line = b"meter,device=Meter power=1 0\n".to_vec();
+        let tagged = with_tags(line, &tags(&[("my system", "garage, west=2"), ("empty", "")]));
+        assert_eq!(
+            String::from_utf8(tagged).unwrap(),
+            "meter,my\\ system=garage\\,\\ west\\=2,empty=unknown,device=Meter power=1 0\n"
+        );
+    }
+
+    #[test]
+    fn with_tags_keeps_lines_without_tags() {
+        let line = b"meter power=1 0\n".to_vec();
+        assert_eq!(with_tags(line.clone(), &tags(&[("system", "home")])), line);
+        let line = b"meter,device=Meter power=1 0\n".to_vec();
+        assert_eq!(with_tags(line.clone(), &[]), line);
+    }
+
+    #[test]
+    fn tag_value_escapes_special_characters() {
+        assert_eq!(tag_value("Symo 8.2-3-M"), "Symo\\ 8.2-3-M");
+        assert_eq!(tag_value("a,b=c"), "a\\,b\\=c");
+        assert_eq!(tag_value(""), "unknown");
+    }
+
+    #[test]
+    fn flux_string_escapes_quotes_and_interpolation() {
+        assert_eq!(flux_string("fronius"), r#""fronius""#);
+        assert_eq!(flux_string(r#"my "bucket""#), r#""my \"bucket\"""#);
+        assert_eq!(flux_string(r"C:\data"), r#""C:\\data""#);
+        assert_eq!(flux_string("${secret}"), r#""\${secret}""#);
+    }
+
+    #[test]
+    fn tag_values_are_only_escaped_in_line_protocol() {
+        let serial = TagValue::from("Haus West, Dach");
+        assert_eq!(serial.encode_key(), "Haus\\ West\\,\\ Dach");
+        assert_eq!(serial.as_str(), "Haus West, Dach");
+        assert_eq!(serde_json::to_value(&serial).unwrap(), "Haus West, Dach");
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use fronius_api::fronius::{self, DeviceId, DeviceType, Fronius};
+
+/// All devices reported by `GetActiveDeviceInfo.cgi`, grouped by device type.
+#[derive(Debug, Default)]
+pub struct Inventory {
+    devices: HashMap<DeviceType, Vec<DeviceId>>,
+    serials: HashMap<(DeviceType, DeviceId), String>,
+}
+
+impl Inventory {
+    pub fn discover(fronius: &Fronius) -> Result<Self, fronius::Error> {
+        let mut devices: HashMap<DeviceType, Vec<DeviceId>> = HashMap::new();
+        let mut serials = HashMap::new();
+        for (device_type, infos) in fronius.get_active_device_info()? {
+            let mut ids = Vec::new();
+            for (id, info) in infos {
+                let Some(id) = id.parse::<u8>().ok().and_then(|id| DeviceId::try_from(id).ok()) else {
+                    continue;
+                };
+                if let Some(info) = info {
+                    serials.insert((device_type, id), info.serial);
+                }
+                ids.push(id);
+            }
+            ids.sort_by_key(|id| u8::from(id));
+            devices.insert(device_type, ids);
+        }
+        Ok(Self { devices, serials })
+    }
+
+    /// Returns the IDs of all discovered devices of the given type, or an empty slice if
+    /// the system has none.
+    pub fn devices(&self, device_type: DeviceType) -> &[DeviceId] {
+        self.devices.get(&device_type).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn serial(&self, device_type: DeviceType, device_id: &DeviceId) -> Option<&str> {
+        self.serials.get(&(device_type, *device_id)).map(String::as_str)
+    }
+}
+
+impl std::fmt::Display for Inventory {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut device_types: Vec<_> = self.devices.iter().filter(|(_, ids)| !ids.is_empty()).collect();
+        device_types.sort_by_key(|(device_type, _)| device_type.param_value());
+        let device_types: Vec<String> = device_types
+            .into_iter()
+            .map(|(device_type, ids)| {
+                let ids: Vec<String> = ids.iter().map(|id| u8::from(id).to_string()).collect();
+                format!("{} [{}]", device_type.param_value(), ids.join(", "))
+            })
+            .collect();
+        write!(f, "{}", device_types.join(", "))
+    }
+}
@@ -0,0 +1,3 @@
+pub mod fronius;
+#[cfg(feature = "mock")]
+pub mod mock;
@@ -1,10 +1,28 @@
-use std::{net::IpAddr, str::FromStr};
+use std::{collections::HashSet, time::Instant};
 
-use fronius::{DeviceId, Fronius};
-use influxdb2::Client;
+use fronius_api::fronius::{self, CapabilityReport, DeviceId, DeviceType, Fronius, SensorChannel, Support, Timestamped};
+use time::OffsetDateTime;
 use influxdb2_derive::WriteDataPoint;
+use serde::Serialize;
 use chrono::prelude::*;
-mod fronius;
+mod backfill;
+mod config;
+mod homeassistant;
+mod influx;
+mod inventory;
+mod mqtt;
+mod prometheus;
+mod sink;
+mod storage_health;
+
+use inventory::Inventory;
+use config::{Config, Endpoint, SystemConfig, TimestampSource};
+use influx::{InfluxSink, TagValue};
+use sink::{Sink, Snapshot};
+use storage_health::StorageHealth;
+
+/// How often the list of connected devices is refreshed.
+const DISCOVERY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
 
 #[derive(Debug)]
 struct OptionEmptyError {
@@ -19,11 +37,95 @@ impl std::fmt::Display for OptionEmptyError {
     }
 }
 
-#[derive(Default, Debug, WriteDataPoint)]
+/// Tags identifying a single inverter in multi-inverter systems.
+struct InverterTags {
+    device_id: String,
+    serial: TagValue,
+    custom_name: TagValue,
+}
+
+fn get_inverter_tags(inventory: &Inventory, infos: Option<&fronius::InverterInfos>, device_id: &DeviceId) -> InverterTags {
+    let info = infos
+        .and_then(|infos| infos.get(&u8::from(device_id).to_string()))
+        .and_then(Option::as_ref);
+    let serial = inventory
+        .serial(DeviceType::Inverter, device_id)
+        .or(info.map(|info| info.unique_id.as_str()))
+        .unwrap_or_default();
+    InverterTags {
+        device_id: u8::from(device_id).to_string(),
+        serial: TagValue::from(serial),
+        custom_name: TagValue::from(info.map(|info| info.custom_name.as_str()).unwrap_or_default()),
+    }
+}
+
+/// Tags identifying a single meter, storage, Ohmpilot, sensor card or string control.
+struct DeviceTags {
+    device_id: String,
+    serial: TagValue,
+}
+
+fn get_device_tags(inventory: &Inventory, device_type: DeviceType, device_id: &DeviceId) -> DeviceTags {
+    DeviceTags {
+        device_id: u8::from(device_id).to_string(),
+        serial: TagValue::from(inventory.serial(device_type, device_id).unwrap_or_default()),
+    }
+}
+
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
+#[measurement = "inverter"]
+struct InverterSystemData {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(field)]
+    ac_power: Option<f64>,
+    #[influxdb(field)]
+    day_energy: Option<f64>,
+    #[influxdb(field)]
+    year_energy: Option<f64>,
+    #[influxdb(field)]
+    total_energy: Option<f64>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+/// Returns the timestamp of a point in nanoseconds, which is the time reported by the
+/// device unless the system is configured to use the local time or the device reported
+/// none.
+fn point_time(source: TimestampSource, device_time: Option<OffsetDateTime>) -> i64 {
+    match (source, device_time) {
+        (TimestampSource::Device, Some(device_time)) => device_time.unix_timestamp_nanos() as i64,
+        _ => Utc::now().timestamp_nanos_opt().expect("Could not fetch timestamp"),
+    }
+}
+
+/// Sums the values of all inverters, or returns `None` if no inverter reports a value.
+fn sum_values(values: &fronius::UnitAndValues<f64>) -> Option<f64> {
+    values.values.values().flatten().copied().reduce(|a, b| a + b)
+}
+
+fn get_inverter_system_data(response: &fronius::CumulationInverterDataSystem, time: i64) -> InverterSystemData {
+    InverterSystemData {
+        device: "System".to_owned(),
+        ac_power: sum_values(&response.pac),
+        day_energy: sum_values(&response.day_energy),
+        year_energy: sum_values(&response.year_energy),
+        total_energy: sum_values(&response.total_energy),
+        time,
+    }
+}
+
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
 #[measurement = "inverter"]
 struct InverterData {
     #[influxdb(tag)]
     device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(tag)]
+    custom_name: TagValue,
     #[influxdb(field)]
     ac_power: Option<f64>,
     #[influxdb(field)]
@@ -45,32 +147,79 @@ struct InverterData {
 }
 
 
-fn get_inverter_data(fronius: &Fronius, device_id: &DeviceId) -> Result<InverterData, Box<dyn std::error::Error>> {
-    let response = fronius.get_inverter_realtime_data_device::<fronius::CommonInverterData>(device_id.to_owned())?;
-
-    let data = InverterData {
+fn get_inverter_data(response: &fronius::CommonInverterData, tags: &InverterTags, time: i64) -> InverterData {
+    InverterData {
         device: "Inverter".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
+        custom_name: tags.custom_name.clone(),
         ac_power: response.pac.value,
         ac_power_abs: response.sac.value,
         ac_current: response.iac.value,
         ac_voltage: response.uac.value,
-        ac_frequency: match response.fac {
+        ac_frequency: match &response.fac {
             None => None,
             Some(a) => a.value,
         } ,
         dc_current: response.idc.value,
         dc_voltage: response.udc.value,
         total_energy: response.total_energy.value,
-        time: Utc::now().timestamp_nanos_opt().expect("Could not fetch timestamp"),
-    };
-    Ok(data)
+        time,
+    }
 }
 
-#[derive(Default, Debug, WriteDataPoint)]
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
+#[measurement = "inverter_mppt"]
+struct InverterMpptData {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(tag)]
+    custom_name: TagValue,
+    #[influxdb(tag)]
+    mppt: String,
+    #[influxdb(field)]
+    dc_current: Option<f64>,
+    #[influxdb(field)]
+    dc_voltage: Option<f64>,
+    #[influxdb(field)]
+    dc_power: Option<f64>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+/// Returns one point per MPP tracker, trackers the inverter does not have are left out.
+fn get_inverter_mppt_data(response: &fronius::CommonInverterData, tags: &InverterTags, time: i64) -> Vec<InverterMpptData> {
+    response.mppt_values().into_iter()
+        .filter(|(_, current, voltage)| current.is_some() || voltage.is_some())
+        .map(|(mppt, current, voltage)| InverterMpptData {
+            device: "Inverter".to_owned(),
+            device_id: tags.device_id.to_owned(),
+            serial: tags.serial.clone(),
+            custom_name: tags.custom_name.clone(),
+            mppt: mppt.to_string(),
+            dc_current: current,
+            dc_voltage: voltage,
+            dc_power: current.zip(voltage).map(|(current, voltage)| current * voltage),
+            time,
+        })
+        .collect()
+}
+
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
 #[measurement = "inverter_phase"]
 struct InverterPhaseData {
     #[influxdb(tag)]
     device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(tag)]
+    custom_name: TagValue,
     #[influxdb(field)]
     ac_l1_current: Option<f64>,
     #[influxdb(field)]
@@ -78,35 +227,125 @@ struct InverterPhaseData {
     #[influxdb(field)]
     ac_l3_current: Option<f64>,
     #[influxdb(field)]
-    dc_l1_voltage: Option<f64>,
+    ac_l1_voltage: Option<f64>,
+    #[influxdb(field)]
+    ac_l2_voltage: Option<f64>,
+    #[influxdb(field)]
+    ac_l3_voltage: Option<f64>,
+    #[influxdb(field)]
+    ac_l1_apparent_power: Option<f64>,
     #[influxdb(field)]
-    dc_l2_voltage: Option<f64>,
+    ac_l2_apparent_power: Option<f64>,
     #[influxdb(field)]
-    dc_l3_voltage: Option<f64>,
+    ac_l3_apparent_power: Option<f64>,
+    #[influxdb(field)]
+    ambient_temperature: Option<f64>,
+    #[influxdb(field)]
+    fan_front_left_speed: Option<f64>,
+    #[influxdb(field)]
+    fan_front_right_speed: Option<f64>,
+    #[influxdb(field)]
+    fan_back_left_speed: Option<f64>,
+    #[influxdb(field)]
+    fan_back_right_speed: Option<f64>,
     #[influxdb(timestamp)]
     time: i64,
 }
 
-fn get_inverter_phase_data(fronius: &Fronius, device_id: &DeviceId) -> Result<InverterPhaseData, Box<dyn std::error::Error>> {
-    let response = fronius.get_inverter_realtime_data_device::<fronius::ThreePhaseInverterData>(device_id.to_owned())?;
+/// The apparent power of a phase, the inverter does not report the power per phase.
+fn phase_power(current: &fronius::UnitAndValue<f64>, voltage: &fronius::UnitAndValue<f64>) -> Option<f64> {
+    current.value.zip(voltage.value).map(|(current, voltage)| current * voltage)
+}
+
+/// The value of an optional value, e.g. of the fans which are only reported by some inverters.
+fn optional_value(value: Option<&fronius::UnitAndValue<f64>>) -> Option<f64> {
+    value.and_then(|value| value.value)
+}
+
+fn get_inverter_phase_data(fronius: &Fronius, device_id: &DeviceId, tags: &InverterTags, source: TimestampSource) -> Result<InverterPhaseData, Box<dyn std::error::Error>> {
+    let Timestamped { timestamp: device_time, data: response } =
+        fronius.timestamped(|fronius| fronius.get_inverter_realtime_data_device::<fronius::ThreePhaseInverterData>(device_id))?;
     let data = InverterPhaseData {
         device: "Inverter".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
+        custom_name: tags.custom_name.clone(),
         ac_l1_current: response.iac_l1.value,
         ac_l2_current: response.iac_l2.value,
         ac_l3_current: response.iac_l3.value,
-        dc_l1_voltage: response.uac_l1.value,
-        dc_l2_voltage: response.uac_l2.value,
-        dc_l3_voltage: response.uac_l3.value,
-        time: Utc::now().timestamp_nanos_opt().expect("Could not fetch timestamp"),
+        ac_l1_voltage: response.uac_l1.value,
+        ac_l2_voltage: response.uac_l2.value,
+        ac_l3_voltage: response.uac_l3.value,
+        ac_l1_apparent_power: phase_power(&response.iac_l1, &response.uac_l1),
+        ac_l2_apparent_power: phase_power(&response.iac_l2, &response.uac_l2),
+        ac_l3_apparent_power: phase_power(&response.iac_l3, &response.uac_l3),
+        ambient_temperature: optional_value(response.t_ambient.as_ref()),
+        fan_front_left_speed: optional_value(response.rotation_speed_fan_fl.as_ref()),
+        fan_front_right_speed: optional_value(response.rotation_speed_fan_fr.as_ref()),
+        fan_back_left_speed: optional_value(response.rotation_speed_fan_bl.as_ref()),
+        fan_back_right_speed: optional_value(response.rotation_speed_fan_br.as_ref()),
+        time: point_time(source, device_time),
+    };
+    Ok(data)
+}
+
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
+#[measurement = "inverter_peak"]
+struct InverterPeakData {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(tag)]
+    custom_name: TagValue,
+    #[influxdb(field)]
+    day_ac_power_max: Option<f64>,
+    #[influxdb(field)]
+    day_ac_voltage_max: Option<f64>,
+    #[influxdb(field)]
+    day_ac_voltage_min: Option<f64>,
+    #[influxdb(field)]
+    day_dc_voltage_max: Option<f64>,
+    #[influxdb(field)]
+    year_ac_power_max: Option<f64>,
+    #[influxdb(field)]
+    total_ac_power_max: Option<f64>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+fn get_inverter_peak_data(fronius: &Fronius, device_id: &DeviceId, tags: &InverterTags, source: TimestampSource) -> Result<InverterPeakData, Box<dyn std::error::Error>> {
+    let Timestamped { timestamp: device_time, data: response } =
+        fronius.timestamped(|fronius| fronius.get_inverter_realtime_data_device::<fronius::MinMaxInverterData>(device_id))?;
+    let data = InverterPeakData {
+        device: "Inverter".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
+        custom_name: tags.custom_name.clone(),
+        day_ac_power_max: response.day_pmax.value,
+        day_ac_voltage_max: response.day_uacmax.value,
+        day_ac_voltage_min: response.day_uacmin.value,
+        day_dc_voltage_max: response.day_udcmax.value,
+        year_ac_power_max: response.year_pmax.value,
+        total_ac_power_max: response.total_pmax.value,
+        time: point_time(source, device_time),
     };
     Ok(data)
 }
 
-#[derive(Default, Debug, WriteDataPoint)]
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
 #[measurement = "inverter_info"]
 struct InverterInfo {
     #[influxdb(tag)]
     device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(tag)]
+    custom_name: TagValue,
     #[influxdb(field)]
     device_type: i64,
     #[influxdb(field)]
@@ -127,12 +366,16 @@ struct InverterInfo {
     time: i64,
 }
 
-fn get_inverter_info(fronius: &Fronius, device_id: &DeviceId) -> Result<InverterInfo, Box<dyn std::error::Error>> {
-    let device_id = u8::from(device_id.to_owned()).to_string();
-    let res = fronius.get_inverter_info()?;
-    let response = res[&device_id].as_ref().expect("Invalid device id");
+fn get_inverter_info(infos: &fronius::InverterInfos, device_id: &DeviceId, tags: &InverterTags, time: i64) -> Result<InverterInfo, Box<dyn std::error::Error>> {
+    let device_id = u8::from(device_id).to_string();
+    let response = infos.get(&device_id).and_then(Option::as_ref).ok_or(OptionEmptyError {
+        variable_name: format!("inverter_info[{device_id}]"),
+    })?;
     let data = InverterInfo {
         device: "Inverter".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
+        custom_name: tags.custom_name.clone(),
         device_type: response.dt,
         pv_power: response.pv_power,
         name: response.custom_name.to_owned(),
@@ -141,16 +384,107 @@ fn get_inverter_info(fronius: &Fronius, device_id: &DeviceId) -> Result<Inverter
         error_code: response.error_code,
         status_code: response.status_code.to_string(),
         state: response.inverter_state.to_owned(),
-        time: Utc::now().timestamp_nanos_opt().expect("Could not fetch timestamp"),
+        time,
     };
     Ok(data)
 }
 
-#[derive(Default, Debug, WriteDataPoint)]
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
+#[measurement = "logger_info"]
+struct LoggerInfo {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(field)]
+    id: Option<String>,
+    #[influxdb(field)]
+    product_id: Option<String>,
+    #[influxdb(field)]
+    platform_id: Option<String>,
+    #[influxdb(field)]
+    hw_version: Option<String>,
+    #[influxdb(field)]
+    sw_version: Option<String>,
+    #[influxdb(field)]
+    time_zone: Option<String>,
+    #[influxdb(field)]
+    time_zone_location: Option<String>,
+    #[influxdb(field)]
+    utc_offset: Option<i64>,
+    #[influxdb(field)]
+    co2_factor: Option<f64>,
+    #[influxdb(field)]
+    co2_unit: Option<String>,
+    #[influxdb(field)]
+    cash_factor: Option<f64>,
+    #[influxdb(field)]
+    cash_currency: Option<String>,
+    #[influxdb(field)]
+    delivery_factor: Option<f64>,
+    #[influxdb(field)]
+    power_led_color: Option<String>,
+    #[influxdb(field)]
+    power_led_state: Option<String>,
+    #[influxdb(field)]
+    solar_net_led_color: Option<String>,
+    #[influxdb(field)]
+    solar_net_led_state: Option<String>,
+    #[influxdb(field)]
+    solar_web_led_color: Option<String>,
+    #[influxdb(field)]
+    solar_web_led_state: Option<String>,
+    #[influxdb(field)]
+    wlan_led_color: Option<String>,
+    #[influxdb(field)]
+    wlan_led_state: Option<String>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+fn get_logger_info(fronius: &Fronius, source: TimestampSource) -> Result<LoggerInfo, Box<dyn std::error::Error>> {
+    let Timestamped { timestamp: device_time, data: response } = fronius.timestamped(Fronius::get_logger_info)?;
+    // GEN24 devices do not offer the LED endpoint, the logger info is still reported without it
+    let leds = fronius.get_logger_led_info().ok();
+    let power_led = leds.as_ref().and_then(|leds| leds.power_led.as_ref());
+    let solar_net_led = leds.as_ref().and_then(|leds| leds.solar_net_led.as_ref());
+    let solar_web_led = leds.as_ref().and_then(|leds| leds.solar_web_led.as_ref());
+    let wlan_led = leds.as_ref().and_then(|leds| leds.wlan_led.as_ref());
+    let data = LoggerInfo {
+        device: "Logger".to_owned(),
+        id: response.unique_id,
+        product_id: response.product_id,
+        platform_id: response.platform_id,
+        hw_version: response.hw_version,
+        sw_version: response.sw_version,
+        time_zone: response.timezone_name,
+        time_zone_location: response.timezone_location,
+        utc_offset: response.utc_offset,
+        co2_factor: response.co2_factor,
+        co2_unit: response.co2_unit,
+        cash_factor: response.cash_factor,
+        cash_currency: response.cash_currency,
+        delivery_factor: response.delivery_factor,
+        power_led_color: power_led.map(|led| led.color.to_owned()),
+        power_led_state: power_led.map(|led| led.state.to_owned()),
+        solar_net_led_color: solar_net_led.map(|led| led.color.to_owned()),
+        solar_net_led_state: solar_net_led.map(|led| led.state.to_owned()),
+        solar_web_led_color: solar_web_led.map(|led| led.color.to_owned()),
+        solar_web_led_state: solar_web_led.map(|led| led.state.to_owned()),
+        wlan_led_color: wlan_led.map(|led| led.color.to_owned()),
+        wlan_led_state: wlan_led.map(|led| led.state.to_owned()),
+        time: point_time(source, device_time),
+    };
+    Ok(data)
+}
+
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
 #[measurement = "meter"]
 struct MeterData {
     #[influxdb(tag)]
     device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
     #[influxdb(field)]
     l1_current: Option<f64>,
     #[influxdb(field)]
@@ -181,14 +515,68 @@ struct MeterData {
     power: f64,
     #[influxdb(field)]
     frequency_average: f64,
+    #[influxdb(field)]
+    l1_apparent_power: Option<f64>,
+    #[influxdb(field)]
+    l2_apparent_power: Option<f64>,
+    #[influxdb(field)]
+    l3_apparent_power: Option<f64>,
+    #[influxdb(field)]
+    apparent_power: f64,
+    #[influxdb(field)]
+    l1_reactive_power: Option<f64>,
+    #[influxdb(field)]
+    l2_reactive_power: Option<f64>,
+    #[influxdb(field)]
+    l3_reactive_power: Option<f64>,
+    #[influxdb(field)]
+    reactive_power: f64,
+    #[influxdb(field)]
+    l1_power_factor: Option<f64>,
+    #[influxdb(field)]
+    l2_power_factor: Option<f64>,
+    #[influxdb(field)]
+    l3_power_factor: Option<f64>,
+    #[influxdb(field)]
+    power_factor: f64,
+    #[influxdb(field)]
+    energy_consumed: f64,
+    #[influxdb(field)]
+    energy_produced: f64,
+    #[influxdb(field)]
+    l1_energy_consumed: Option<f64>,
+    #[influxdb(field)]
+    l2_energy_consumed: Option<f64>,
+    #[influxdb(field)]
+    l3_energy_consumed: Option<f64>,
+    #[influxdb(field)]
+    l1_energy_produced: Option<f64>,
+    #[influxdb(field)]
+    l2_energy_produced: Option<f64>,
+    #[influxdb(field)]
+    l3_energy_produced: Option<f64>,
+    #[influxdb(field)]
+    energy_plus_absolute: f64,
+    #[influxdb(field)]
+    energy_minus_absolute: Option<f64>,
+    #[influxdb(field)]
+    reactive_energy_consumed: Option<f64>,
+    #[influxdb(field)]
+    reactive_energy_produced: Option<f64>,
+    #[influxdb(field)]
+    l1_reactive_energy_consumed: Option<f64>,
+    #[influxdb(field)]
+    l1_reactive_energy_produced: Option<f64>,
     #[influxdb(timestamp)]
     time: i64,
 }
 
-fn get_meter_data(fronius: &Fronius, device_id: &DeviceId) -> Result<MeterData, Box<dyn std::error::Error>> {
+fn get_meter_data(fronius: &Fronius, device_id: &DeviceId, tags: &DeviceTags, source: TimestampSource) -> Result<MeterData, Box<dyn std::error::Error>> {
     let response = fronius.get_meter_realtime_data_device(device_id)?;
     let data = MeterData {
         device: "Meter".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
         l1_current: response.current_ac_phase_1,
         l2_current: response.current_ac_phase_2,
         l3_current: response.current_ac_phase_3,
@@ -204,16 +592,46 @@ fn get_meter_data(fronius: &Fronius, device_id: &DeviceId) -> Result<MeterData,
         l3_power: response.power_real_p_phase_3,
         power: response.power_real_p_sum,
         frequency_average: response.frequency_phase_average,
-        time: Utc::now().timestamp_nanos_opt().expect("Could not fetch timestamp"),
+        l1_apparent_power: response.power_apparent_s_phase_1,
+        l2_apparent_power: response.power_apparent_s_phase_2,
+        l3_apparent_power: response.power_apparent_s_phase_3,
+        apparent_power: response.power_apparent_s_sum,
+        l1_reactive_power: response.power_reactive_q_phase_1,
+        l2_reactive_power: response.power_reactive_q_phase_2,
+        l3_reactive_power: response.power_reactive_q_phase_3,
+        reactive_power: response.power_reactive_q_sum,
+        l1_power_factor: response.power_factor_phase_1,
+        l2_power_factor: response.power_factor_phase_2,
+        l3_power_factor: response.power_factor_phase_3,
+        power_factor: response.power_factor_sum,
+        energy_consumed: response.energy_real_wac_sum_consumed,
+        energy_produced: response.energy_real_wac_sum_produced,
+        l1_energy_consumed: response.energy_real_wac_phase_1_consumed,
+        l2_energy_consumed: response.energy_real_wac_phase_2_consumed,
+        l3_energy_consumed: response.energy_real_wac_phase_3_consumed,
+        l1_energy_produced: response.energy_real_wac_phase_1_produced,
+        l2_energy_produced: response.energy_real_wac_phase_2_produced,
+        l3_energy_produced: response.energy_real_wac_phase_3_produced,
+        energy_plus_absolute: response.energy_real_wac_plus_absolute,
+        energy_minus_absolute: response.energy_real_wac_minus_absolute,
+        reactive_energy_consumed: response.energy_reactive_va_r_ac_sum_consumed,
+        reactive_energy_produced: response.energy_reactive_va_r_ac_sum_produced,
+        l1_reactive_energy_consumed: response.energy_reactive_va_r_ac_phase_1_consumed,
+        l1_reactive_energy_produced: response.energy_reactive_va_r_ac_phase_1_produced,
+        time: point_time(source, Some(response.time_stamp)),
     };
     Ok(data)
 }
 
-#[derive(Default, Debug, WriteDataPoint)]
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
 #[measurement = "storage"]
 struct StorageData {
     #[influxdb(tag)]
     device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
     #[influxdb(field)]
     enabled: bool,
     #[influxdb(field)]
@@ -230,26 +648,241 @@ struct StorageData {
     time: i64,
 }
 
-fn get_storage_data(fronius: &Fronius, device_id: &DeviceId) -> Result<StorageData, Box<dyn std::error::Error>> {
-    let response = fronius.get_storage_realtime_data_device(device_id)?;
-    let data = StorageData {
+fn get_storage_data(response: &fronius::StorageData, tags: &DeviceTags, source: TimestampSource) -> StorageData {
+    StorageData {
         device: "Storage".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
         enabled: response.controller.enable > 0,
         charge_percentage: response.controller.state_of_charge_relative,
         capacity: response.controller.capacity_maximum,
         dc_current: response.controller.current_dc,
         dc_voltage: response.controller.voltage_dc,
         temperature_cell: response.controller.temperature_cell,
-        time: Utc::now().timestamp_nanos_opt().expect("Could not fetch timestamp"),
+        time: point_time(source, Some(response.controller.time_stamp)),
+    }
+}
+
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
+#[measurement = "storage_module"]
+struct StorageModuleData {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    module: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(field)]
+    enabled: Option<bool>,
+    #[influxdb(field)]
+    charge_percentage: Option<f64>,
+    #[influxdb(field)]
+    capacity: Option<f64>,
+    #[influxdb(field)]
+    designed_capacity: Option<f64>,
+    #[influxdb(field)]
+    cycle_count: Option<f64>,
+    #[influxdb(field)]
+    status: Option<i64>,
+    #[influxdb(field)]
+    dc_current: Option<f64>,
+    #[influxdb(field)]
+    dc_voltage: Option<f64>,
+    #[influxdb(field)]
+    cell_voltage_max: Option<f64>,
+    #[influxdb(field)]
+    cell_voltage_min: Option<f64>,
+    #[influxdb(field)]
+    cell_voltage_spread: Option<f64>,
+    #[influxdb(field)]
+    temperature_cell: Option<f64>,
+    #[influxdb(field)]
+    temperature_cell_max: Option<f64>,
+    #[influxdb(field)]
+    temperature_cell_min: Option<f64>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+/// Returns one point per battery module, `module` is the position of the module in the
+/// response and tells modules without serial apart.
+fn get_storage_module_data(response: &fronius::StorageData, tags: &DeviceTags, source: TimestampSource) -> Vec<StorageModuleData> {
+    response.modules.iter().enumerate().map(|(module, data)| StorageModuleData {
+        device: "StorageModule".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        module: module.to_string(),
+        serial: TagValue::from(data.details.as_ref().map(|details| details.serial.as_str()).unwrap_or_default()),
+        enabled: data.enable.map(|enable| enable > 0),
+        charge_percentage: data.state_of_charge_relative,
+        capacity: data.capacity_maximum,
+        designed_capacity: data.designed_capacity,
+        cycle_count: data.cycle_count_battery_cell,
+        status: data.status_battery_cell.map(|status| status as i64),
+        dc_current: data.current_dc,
+        dc_voltage: data.voltage_dc,
+        cell_voltage_max: data.voltage_dc_maximum_cell,
+        cell_voltage_min: data.voltage_dc_minimum_cell,
+        cell_voltage_spread: data.voltage_dc_maximum_cell.zip(data.voltage_dc_minimum_cell).map(|(max, min)| max - min),
+        temperature_cell: data.temperature_cell,
+        temperature_cell_max: data.temperature_cell_maximum,
+        temperature_cell_min: data.temperature_cell_minimum,
+        time: point_time(source, Some(data.time_stamp)),
+    }).collect()
+}
+
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
+#[measurement = "storage_health"]
+struct StorageHealthData {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(field)]
+    capacity: f64,
+    #[influxdb(field)]
+    designed_capacity: Option<f64>,
+    #[influxdb(field)]
+    state_of_health: Option<f64>,
+    #[influxdb(field)]
+    cycle_count: Option<f64>,
+    #[influxdb(field)]
+    charged_energy: f64,
+    #[influxdb(field)]
+    discharged_energy: f64,
+    #[influxdb(field)]
+    equivalent_full_cycles: Option<f64>,
+    #[influxdb(field)]
+    day_charged_energy: f64,
+    #[influxdb(field)]
+    day_discharged_energy: f64,
+    #[influxdb(field)]
+    day_round_trip_efficiency: Option<f64>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+/// Derives the state of health from the capacities reported by the battery and the
+/// cycles from the DC power integrated by `storage_health`.
+fn get_storage_health_data(response: &fronius::StorageData, storage_health: &mut StorageHealth, device_id: &DeviceId, tags: &DeviceTags, source: TimestampSource) -> StorageHealthData {
+    let controller = &response.controller;
+    // Not every controller reports its designed capacity, the modules usually do
+    let designed_capacity = controller.designed_capacity.or_else(|| {
+        response.modules.iter().filter_map(|module| module.designed_capacity).reduce(|a, b| a + b)
+    });
+    let throughput = storage_health.record(*device_id, controller.time_stamp, controller.current_dc * controller.voltage_dc);
+    StorageHealthData {
+        device: "Storage".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
+        capacity: controller.capacity_maximum,
+        designed_capacity,
+        state_of_health: designed_capacity
+            .filter(|designed_capacity| *designed_capacity > 0.0)
+            .map(|designed_capacity| controller.capacity_maximum / designed_capacity * 100.0),
+        cycle_count: response.modules.iter().filter_map(|module| module.cycle_count_battery_cell).reduce(f64::max),
+        charged_energy: throughput.charged_energy,
+        discharged_energy: throughput.discharged_energy,
+        // The designed capacity doesn't fade, so the cycles only ever grow like a counter
+        equivalent_full_cycles: designed_capacity
+            .filter(|designed_capacity| *designed_capacity > 0.0)
+            .map(|designed_capacity| (throughput.charged_energy + throughput.discharged_energy) / 2.0 / designed_capacity),
+        day_charged_energy: throughput.day_charged_energy,
+        day_discharged_energy: throughput.day_discharged_energy,
+        day_round_trip_efficiency: Some(throughput.day_charged_energy)
+            .filter(|charged_energy| *charged_energy > 0.0)
+            .map(|charged_energy| throughput.day_discharged_energy / charged_energy * 100.0),
+        time: point_time(source, Some(controller.time_stamp)),
+    }
+}
+
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
+#[measurement = "sensor"]
+struct SensorData {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(field)]
+    module_temperature: Option<f64>,
+    #[influxdb(field)]
+    ambient_temperature: Option<f64>,
+    #[influxdb(field)]
+    irradiance: Option<f64>,
+    #[influxdb(field)]
+    wind_speed: Option<f64>,
+    #[influxdb(field)]
+    digital_2: Option<f64>,
+    #[influxdb(field)]
+    current_loop: Option<f64>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+fn get_sensor_data(fronius: &Fronius, device_id: &DeviceId, tags: &DeviceTags, source: TimestampSource) -> Result<SensorData, Box<dyn std::error::Error>> {
+    let Timestamped { timestamp: device_time, data: response } =
+        fronius.timestamped(|fronius| fronius.get_sensor_realtime_data::<fronius::NowSensorData>(device_id))?;
+    let data = SensorData {
+        device: "Sensor".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
+        module_temperature: response.value(SensorChannel::ModuleTemperature),
+        ambient_temperature: response.value(SensorChannel::AmbientTemperature),
+        irradiance: response.value(SensorChannel::Irradiance),
+        wind_speed: response.value(SensorChannel::WindSpeed),
+        digital_2: response.value(SensorChannel::Digital2),
+        current_loop: response.value(SensorChannel::CurrentLoop),
+        time: point_time(source, device_time),
     };
     Ok(data)
 }
 
-#[derive(Default, Debug, WriteDataPoint)]
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
+#[measurement = "string_control"]
+struct StringControlData {
+    #[influxdb(tag)]
+    device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
+    #[influxdb(tag)]
+    string: String,
+    #[influxdb(field)]
+    current: Option<f64>,
+    #[influxdb(timestamp)]
+    time: i64,
+}
+
+fn get_string_control_data(fronius: &Fronius, device_id: &DeviceId, tags: &DeviceTags, source: TimestampSource) -> Result<Vec<StringControlData>, Box<dyn std::error::Error>> {
+    let Timestamped { timestamp: device_time, data: response } =
+        fronius.timestamped(|fronius| fronius.get_string_realtime_data::<fronius::NowStringControlData>(device_id))?;
+    let time = point_time(source, device_time);
+    let data = response.values().into_iter().map(|(string, current)| StringControlData {
+        device: "StringControl".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
+        string: string.to_string(),
+        current,
+        time,
+    }).collect();
+    Ok(data)
+}
+
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
 #[measurement = "ohm_pilot"]
 struct OhmPilotData {
     #[influxdb(tag)]
     device: String,
+    #[influxdb(tag)]
+    device_id: String,
+    #[influxdb(tag)]
+    serial: TagValue,
     #[influxdb(field)]
     state: String,
     #[influxdb(field)]
@@ -262,20 +895,23 @@ struct OhmPilotData {
     time: i64,
 }
 
-fn get_ohm_pilot_data(fronius: &Fronius, device_id: &DeviceId) -> Result<OhmPilotData, Box<dyn std::error::Error>> {
-    let response = fronius.get_ohm_pilot_realtime_data_device(device_id)?;
+fn get_ohm_pilot_data(fronius: &Fronius, device_id: &DeviceId, tags: &DeviceTags, source: TimestampSource) -> Result<OhmPilotData, Box<dyn std::error::Error>> {
+    let Timestamped { timestamp: device_time, data: response } =
+        fronius.timestamped(|fronius| fronius.get_ohm_pilot_realtime_data_device(device_id))?;
     let data = OhmPilotData {
         device: "OhmPilot".to_owned(),
+        device_id: tags.device_id.to_owned(),
+        serial: tags.serial.clone(),
         state: response.code_of_state.to_string(),
         error_code: response.code_of_error.unwrap_or(0),
         power: response.power_real_pac_sum,
         temperature: response.temperature_channel_1,
-        time: Utc::now().timestamp_nanos_opt().expect("Could not fetch timestamp"),
+        time: point_time(source, device_time),
     };
     Ok(data)
 }
 
-#[derive(Default, Debug, WriteDataPoint)]
+#[derive(Default, Debug, Serialize, WriteDataPoint)]
 #[measurement = "power_flow"]
 struct PowerFlowData {
     #[influxdb(tag)]
@@ -297,8 +933,8 @@ struct PowerFlowData {
 }
 
 
-fn get_power_flow_data(fronius: &Fronius) -> Result<PowerFlowData, Box<dyn std::error::Error>> {
-    let response = fronius.get_power_flow_realtime_data()?;
+fn get_power_flow_data(fronius: &Fronius, source: TimestampSource) -> Result<PowerFlowData, Box<dyn std::error::Error>> {
+    let Timestamped { timestamp: device_time, data: response } = fronius.timestamped(Fronius::get_power_flow_realtime_data)?;
     let data = PowerFlowData {
         device: "Unknown".to_owned(),
         akku: response.site.p_akku,
@@ -307,100 +943,291 @@ fn get_power_flow_data(fronius: &Fronius) -> Result<PowerFlowData, Box<dyn std::
         photovoltaik: response.site.p_pv,
         relative_autonomy: response.site.rel_autonomy,
         relative_self_consumption: response.site.rel_self_consumption,
-        time: Utc::now().timestamp_nanos_opt().expect("Could not fetch timestamp"),
+        time: point_time(source, device_time),
     };
     Ok(data)
 }
 
-fn send_to_influx(client: &Client, bucket: &str,  data: impl futures::Stream<Item = impl influxdb2::models::WriteDataPoint> + Send + Sync + 'static){
-    let res = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(client.write(bucket, data));
+/// Fetches the data of every enabled endpoint, except the endpoints in `unsupported`.
+fn fetch_data(fronius: &Fronius, inventory: &Inventory, config: &SystemConfig, unsupported: &HashSet<Endpoint>, system_scope_failing: &mut bool, storage_health: &mut StorageHealth) -> Snapshot {
+    let mut snapshot = Snapshot {
+        system: config.name.clone(),
+        tags: config.all_tags(),
+        ..Snapshot::default()
+    };
+    let is_enabled = |endpoint| config.is_enabled(endpoint) && !unsupported.contains(&endpoint);
 
-    if let Err(error) = res {
-        println!("Error during influxdb write occured: {:?}", error);
+    // The system scope reports every inverter of a multi-inverter system, the inventory
+    // is only used if the system scope is not available.
+    let source = config.timestamps;
+    let inverter_ids = match fronius.timestamped(Fronius::get_inverter_realtime_data_system) {
+        Ok(Timestamped { timestamp: device_time, data: system }) => {
+            *system_scope_failing = false;
+            if is_enabled(Endpoint::Inverter) {
+                snapshot.push(Endpoint::Inverter, "inverter_system_data", None, Ok(get_inverter_system_data(&system, point_time(source, device_time))));
+            }
+            let mut ids: Vec<DeviceId> = system.pac.values.keys()
+                .filter_map(|id| DeviceId::try_from(id.parse::<u8>().ok()?).ok())
+                .collect();
+            ids.sort_by_key(|id| u8::from(id));
+            ids
+        }
+        Err(error) => {
+            // Not every device has a system scope, other errors are only logged once
+            // until the system scope answers again
+            if !error.is_unsupported() && !*system_scope_failing {
+                println!("Error during fetch of inverter_system_data occured, using the discovered inverters: {:?}", error);
+            }
+            *system_scope_failing = true;
+            inventory.devices(DeviceType::Inverter).to_vec()
+        }
+    };
+    let inverter_infos = fronius.timestamped(Fronius::get_inverter_info);
+    for inverter_id in &inverter_ids {
+        let tags = get_inverter_tags(inventory, inverter_infos.as_ref().ok().map(|infos| &infos.data), inverter_id);
+        if is_enabled(Endpoint::Inverter) {
+            // `inverter` and `inverter_mppt` are both read from `CommonInverterData`
+            match fronius.timestamped(|fronius| fronius.get_inverter_realtime_data_device::<fronius::CommonInverterData>(inverter_id)) {
+                Ok(Timestamped { timestamp: device_time, data: response }) => {
+                    let time = point_time(source, device_time);
+                    snapshot.push(Endpoint::Inverter, "inverter_data", Some(inverter_id), Ok(get_inverter_data(&response, &tags, time)));
+                    snapshot.push_all(Endpoint::Inverter, "inverter_mppt_data", Some(inverter_id), Ok(get_inverter_mppt_data(&response, &tags, time)));
+                }
+                Err(error) => snapshot.push::<InverterData>(Endpoint::Inverter, "inverter_data", Some(inverter_id), Err(error.into())),
+            }
+        }
+        if is_enabled(Endpoint::InverterPhase) {
+            snapshot.push(Endpoint::InverterPhase, "inverter_phase_data", Some(inverter_id), get_inverter_phase_data(fronius, inverter_id, &tags, source));
+        }
+        if is_enabled(Endpoint::InverterPeak) {
+            snapshot.push(Endpoint::InverterPeak, "inverter_peak_data", Some(inverter_id), get_inverter_peak_data(fronius, inverter_id, &tags, source));
+        }
+        if is_enabled(Endpoint::InverterInfo) {
+            match &inverter_infos {
+                Ok(infos) => snapshot.push(Endpoint::InverterInfo, "inverter_info", Some(inverter_id), get_inverter_info(&infos.data, inverter_id, &tags, point_time(source, infos.timestamp))),
+                Err(error) => println!("Error during fetch of inverter_info occured: {:?}", error),
+            }
+        }
     }
-}
 
+    if is_enabled(Endpoint::LoggerInfo) {
+        snapshot.push(Endpoint::LoggerInfo, "logger_info", None, get_logger_info(fronius, source));
+    }
 
+    if is_enabled(Endpoint::Meter) {
+        for meter_id in inventory.devices(DeviceType::Meter) {
+            let tags = get_device_tags(inventory, DeviceType::Meter, meter_id);
+            snapshot.push(Endpoint::Meter, "meter_data", Some(meter_id), get_meter_data(fronius, meter_id, &tags, source));
+        }
+    }
 
-fn fetch_data(fronius: &Fronius) -> Result<(), Box<dyn std::error::Error>> {
-    let interver_id = DeviceId::try_from(1).unwrap();
-    let meter_id = DeviceId::try_from(0).unwrap();
-    let storage_id = DeviceId::try_from(0).unwrap();
-    let ohm_pilot_id = DeviceId::try_from(0).unwrap();
-    let inverter_data = get_inverter_data(fronius, &interver_id);
-    let inverter_phase_data = get_inverter_phase_data(fronius, &interver_id);
-    let inverter_info = get_inverter_info(fronius, &interver_id);
-    let meter_data = get_meter_data(fronius, &meter_id);
-    let storage_data = get_storage_data(fronius, &storage_id);
-    let ohm_pilot_data = get_ohm_pilot_data(fronius, &ohm_pilot_id);
-    let power_flow_data = get_power_flow_data(fronius);
+    if is_enabled(Endpoint::Storage) {
+        for storage_id in inventory.devices(DeviceType::Storage) {
+            let tags = get_device_tags(inventory, DeviceType::Storage, storage_id);
+            match fronius.get_storage_realtime_data_device(storage_id) {
+                Ok(response) => {
+                    snapshot.push(Endpoint::Storage, "storage_data", Some(storage_id), Ok(get_storage_data(&response, &tags, source)));
+                    snapshot.push_all(Endpoint::Storage, "storage_module_data", Some(storage_id), Ok(get_storage_module_data(&response, &tags, source)));
+                    snapshot.push(Endpoint::Storage, "storage_health_data", Some(storage_id), Ok(get_storage_health_data(&response, storage_health, storage_id, &tags, source)));
+                }
+                Err(error) => snapshot.push::<StorageData>(Endpoint::Storage, "storage_data", Some(storage_id), Err(error.into())),
+            }
+        }
+    }
 
-    let client = Client::new(std::env::var("INFLUX_DB_URL")?, std::env::var("INFLUX_DB_ORG")?, std::env::var("INFLUX_DB_TOKEN")?);
-    let bucket = std::env::var("INFLUX_DB_BUCKET")?;
+    if is_enabled(Endpoint::OhmPilot) {
+        for ohm_pilot_id in inventory.devices(DeviceType::Ohmpilot) {
+            let tags = get_device_tags(inventory, DeviceType::Ohmpilot, ohm_pilot_id);
+            snapshot.push(Endpoint::OhmPilot, "ohm_pilot_data", Some(ohm_pilot_id), get_ohm_pilot_data(fronius, ohm_pilot_id, &tags, source));
+        }
+    }
 
-    if let Ok(val) = inverter_data {
-        send_to_influx(&client, &bucket, futures::stream::iter(vec![val]));
-    }else if let Err(error) = inverter_data {
-        println!("Error during fetch of inverter_data occured: {:?}", error);
+    if is_enabled(Endpoint::Sensor) {
+        for sensor_id in inventory.devices(DeviceType::SensorCard) {
+            let tags = get_device_tags(inventory, DeviceType::SensorCard, sensor_id);
+            snapshot.push(Endpoint::Sensor, "sensor_data", Some(sensor_id), get_sensor_data(fronius, sensor_id, &tags, source));
+        }
     }
 
-    if let Ok(val) = inverter_phase_data {
-        send_to_influx(&client, &bucket, futures::stream::iter(vec![val]));
-    }else if let Err(error) = inverter_phase_data {
-        println!("Error during fetch of inverter_phase_data occured: {:?}", error);
+    if is_enabled(Endpoint::StringControl) {
+        for string_control_id in inventory.devices(DeviceType::StringControl) {
+            let tags = get_device_tags(inventory, DeviceType::StringControl, string_control_id);
+            snapshot.push_all(Endpoint::StringControl, "string_control_data", Some(string_control_id), get_string_control_data(fronius, string_control_id, &tags, source));
+        }
     }
 
-    if let Ok(val) = inverter_info {
-        send_to_influx(&client, &bucket, futures::stream::iter(vec![val]));
-    }else if let Err(error) = inverter_info {
-        println!("Error during fetch of inverter_info occured: {:?}", error);
+    if is_enabled(Endpoint::PowerFlow) {
+        snapshot.push(Endpoint::PowerFlow, "power_flow_data", None, get_power_flow_data(fronius, source));
     }
 
-    if let Ok(val) = meter_data {
-        send_to_influx(&client, &bucket, futures::stream::iter(vec![val]));
-    }else if let Err(error) = meter_data {
-        println!("Error during fetch of meter_data occured: {:?}", error);
+    snapshot
+}
+
+/// A configured Fronius system and the state of its connection.
+struct System {
+    config: SystemConfig,
+    connection: Option<Connection>,
+    next_poll: Instant,
+    /// Kept across reconnects and seeded from InfluxDB on startup, if configured.
+    storage_health: StorageHealth,
+}
+
+struct Connection {
+    fronius: Fronius,
+    inventory: Inventory,
+    last_discovery: Instant,
+    /// Endpoints the device does not support according to the capability probe or
+    /// because it answered with `NotImplemented` or `NotSupported`. They are not polled
+    /// until the devices are rediscovered.
+    unsupported: HashSet<Endpoint>,
+    /// Whether the last request of the system scope of the inverters failed.
+    system_scope_failing: bool,
+}
+
+impl System {
+    fn new(config: SystemConfig) -> Self {
+        let storage_health = StorageHealth::new(config.poll_interval());
+        Self {
+            config,
+            connection: None,
+            next_poll: Instant::now(),
+            storage_health,
+        }
     }
 
-    if let Ok(val) = storage_data {
-        send_to_influx(&client, &bucket, futures::stream::iter(vec![val]));
-    }else if let Err(error) = storage_data {
-        println!("Error during fetch of storage_data occured: {:?}", error);
+    /// Connects to the system if not connected yet and refreshes the list of connected
+    /// devices once `DISCOVERY_INTERVAL` elapsed.
+    fn connect(&mut self, sinks: &mut [Box<dyn Sink>]) -> Result<(), Box<dyn std::error::Error>> {
+        let name = self.config.name.as_deref();
+        match &mut self.connection {
+            Some(connection) => {
+                if connection.last_discovery.elapsed() >= DISCOVERY_INTERVAL {
+                    match Inventory::discover(&connection.fronius) {
+                        Ok(discovered) => {
+                            connection.inventory = discovered;
+                            connection.unsupported = unsupported_endpoints(&connection.fronius.probe_capabilities());
+                            println!("Discovered devices of {}: {}", self.config.label(), connection.inventory);
+                            devices_discovered(name, connection, sinks);
+                        }
+                        Err(error) => println!("Error during device discovery occured: {:?}", error),
+                    }
+                    connection.last_discovery = Instant::now();
+                }
+            }
+            None => {
+                let fronius = self.config.connect()?;
+                let inventory = Inventory::discover(&fronius)?;
+                println!("Discovered devices of {}: {inventory}", self.config.label());
+                let capabilities = fronius.probe_capabilities();
+                print!("Capabilities of {}:\n{capabilities}", self.config.label());
+                let unsupported = unsupported_endpoints(&capabilities);
+                let mut skipped: Vec<&str> = self.config.endpoints.iter()
+                    .filter(|endpoint| unsupported.contains(endpoint))
+                    .map(|endpoint| endpoint.name())
+                    .collect();
+                skipped.sort();
+                if !skipped.is_empty() {
+                    println!("Not polling unsupported endpoints of {}: {}", self.config.label(), skipped.join(", "));
+                }
+                let connection = Connection {
+                    fronius,
+                    inventory,
+                    last_discovery: Instant::now(),
+                    unsupported,
+                    system_scope_failing: false,
+                };
+                devices_discovered(name, &connection, sinks);
+                self.connection = Some(connection);
+            }
+        }
+        Ok(())
     }
 
-    if let Ok(val) = ohm_pilot_data {
-        send_to_influx(&client, &bucket, futures::stream::iter(vec![val]));
-    }else if let Err(error) = ohm_pilot_data {
-        println!("Error during fetch of ohm_pilot_data occured: {:?}", error);
+    fn poll(&mut self, sinks: &mut [Box<dyn Sink>]) {
+        self.next_poll = Instant::now() + self.config.poll_interval();
+        if let Err(error) = self.connect(sinks) {
+            println!("Error during connect to {} occured: {:?}", self.config.label(), error);
+            return;
+        }
+        let connection = self.connection.as_mut().expect("Connection was established by connect");
+
+        println!("Reporting data of {} at: {}", self.config.label(), Utc::now());
+        let snapshot = fetch_data(&connection.fronius, &connection.inventory, &self.config, &connection.unsupported, &mut connection.system_scope_failing, &mut self.storage_health);
+        for endpoint in &snapshot.unsupported {
+            if connection.unsupported.insert(*endpoint) {
+                println!("{} does not support {}, it is no longer polled", self.config.label(), endpoint.name());
+            }
+        }
+        for sink in sinks.iter_mut() {
+            if let Err(error) = sink.write(&snapshot) {
+                println!("Error during {} write occured: {:?}", sink.name(), error);
+            }
+        }
     }
+}
 
-    if let Ok(val) = power_flow_data {
-        send_to_influx(&client, &bucket, futures::stream::iter(vec![val]));
-    }else if let Err(error) = power_flow_data {
-        println!("Error during fetch of power_flow_data occured: {:?}", error);
+/// Returns the endpoints the device reported as not supported. Endpoints that could not
+/// be probed, e.g. because of a timeout, are still polled.
+fn unsupported_endpoints(capabilities: &CapabilityReport) -> HashSet<Endpoint> {
+    Endpoint::ALL
+        .into_iter()
+        .filter(|endpoint| capabilities.support(endpoint.capability()) == Support::Unsupported)
+        .collect()
+}
+
+fn devices_discovered(system: Option<&str>, connection: &Connection, sinks: &mut [Box<dyn Sink>]) {
+    for sink in sinks {
+        if let Err(error) = sink.devices_discovered(system, &connection.fronius, &connection.inventory) {
+            println!("Error during {} device announcement occured: {:?}", sink.name(), error);
+        }
     }
+}
 
-    Ok(())
+fn run_backfill(system: &SystemConfig, influx: &InfluxSink, max_days: i64) -> Result<(), Box<dyn std::error::Error>> {
+    let fronius = system.connect()?;
+    let inventory = Inventory::discover(&fronius)?;
+    backfill::run(&fronius, influx, &inventory, &system.all_tags(), max_days)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let ip_str = std::env::var("FRONIUS_IP")?;
-    let ip = IpAddr::V4(std::net::Ipv4Addr::from_str(&ip_str)?);
-    let fronius = Fronius::connect(ip)?;
+    let config = Config::load()?;
+
+    if std::env::args().nth(1).as_deref() == Some("backfill") {
+        let influx = InfluxSink::new(config.influxdb.as_ref().ok_or("Backfill requires InfluxDB to be configured")?)?;
+        for system in &config.systems {
+            run_backfill(system, &influx, config.backfill.max_days)?;
+        }
+        return Ok(());
+    }
+    if config.backfill.on_startup {
+        if let Some(influxdb) = &config.influxdb {
+            let influx = InfluxSink::new(influxdb)?;
+            for system in &config.systems {
+                if let Err(error) = run_backfill(system, &influx, config.backfill.max_days) {
+                    println!("Error during backfill of {} occured: {:?}", system.label(), error);
+                }
+            }
+        }
+    }
+
+    let mut sinks = sink::from_config(&config)?;
+    let mut systems: Vec<System> = config.systems.into_iter().map(System::new).collect();
+    if let Some(influxdb) = &config.influxdb {
+        let influx = InfluxSink::new(influxdb)?;
+        for system in systems.iter_mut() {
+            if let Err(error) = system.storage_health.seed_from_influx(&influx, &system.config.all_tags()) {
+                println!("Error during lookup of storage_health of {} occured: {:?}", system.config.label(), error);
+            }
+        }
+    }
+
     loop {
-        let now = Utc::now();
-        println!("Reporting data at: {now}");
-        let res = fetch_data(&fronius);
-
-        if let Err(error) = res {
-            println!("Error during fetch occured: {:?}", error);
-        }else{
-            res?;
+        for system in systems.iter_mut() {
+            if system.next_poll <= Instant::now() {
+                system.poll(&mut sinks);
+            }
         }
-        std::thread::sleep(std::time::Duration::from_secs(15));
+        let next_poll = systems.iter().map(|system| system.next_poll).min().expect("At least one system is configured");
+        std::thread::sleep(next_poll.saturating_duration_since(Instant::now()));
     }
 }
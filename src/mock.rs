@@ -0,0 +1,212 @@
+//! A local stand-in for the Fronius Solar API, used by the integration tests and the
+//! `mock_server` binary.
+//!
+//! Responses are served from JSON fixtures. A fixture holds the `Body` of a response,
+//! the mock adds the `Head` itself. Fixtures are looked up by the endpoint name without
+//! extension, followed by the `Scope` and `DataCollection` parameters if present, e.g.
+//! `GetInverterRealtimeData.Device.CommonInverterData`, then
+//! `GetInverterRealtimeData.Device` and finally `GetInverterRealtimeData`. The fixture
+//! `GetAPIVersion` is served as is.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use reqwest::Url;
+use time::OffsetDateTime;
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::fronius::StatusCode;
+
+/// Misbehaviour injected into the responses of one endpoint.
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// Answer with the given Fronius status code and an empty body.
+    Status(StatusCode),
+    /// Wait before answering.
+    Delay(Duration),
+    /// Answer with a body that is not valid JSON.
+    Malformed,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    fixtures: HashMap<String, serde_json::Value>,
+    faults: HashMap<String, Fault>,
+    requests: HashMap<String, usize>,
+}
+
+pub struct MockServer {
+    server: Arc<Server>,
+    state: Arc<Mutex<State>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Starts a mock server on a random local port serving the fixtures in `fixture_dir`.
+    pub fn start(fixture_dir: impl AsRef<Path>) -> io::Result<Self> {
+        Self::bind("127.0.0.1:0", fixture_dir)
+    }
+
+    pub fn bind(addr: impl ToSocketAddrs, fixture_dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut state = State::default();
+        for entry in std::fs::read_dir(fixture_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let fixture = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+                state.fixtures.insert(name.to_string(), fixture);
+            }
+        }
+
+        let server = Server::http(addr).map_err(io::Error::other)?;
+        let server = Arc::new(server);
+        let state = Arc::new(Mutex::new(state));
+
+        let thread = {
+            let server = server.clone();
+            let state = state.clone();
+            std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let state = state.clone();
+                    // Every request is answered on its own thread so a delayed response
+                    // does not hold back the others.
+                    std::thread::spawn(move || handle(&state, request));
+                }
+            })
+        };
+
+        Ok(Self {
+            server,
+            state,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.server
+            .server_addr()
+            .to_ip()
+            .expect("Mock server should listen on an IP address")
+    }
+
+    /// Returns the URL of the mock device, to be passed to [`crate::fronius::Fronius::connect_url`].
+    pub fn url(&self) -> Url {
+        Url::parse(&format!("http://{}/", self.addr())).expect("Mock server URL should be valid")
+    }
+
+    /// Replaces or adds the fixture with the given name.
+    pub fn set_fixture(&self, name: &str, body: serde_json::Value) {
+        self.state.lock().unwrap().fixtures.insert(name.to_string(), body);
+    }
+
+    /// Injects a fault into every response of `endpoint`, given without extension
+    /// (e.g. `GetMeterRealtimeData`).
+    pub fn inject_fault(&self, endpoint: &str, fault: Fault) {
+        self.state.lock().unwrap().faults.insert(endpoint.to_string(), fault);
+    }
+
+    pub fn clear_faults(&self) {
+        self.state.lock().unwrap().faults.clear();
+    }
+
+    /// Returns how often the endpoint was requested, e.g. to check retries.
+    pub fn request_count(&self, endpoint: &str) -> usize {
+        self.state.lock().unwrap().requests.get(endpoint).copied().unwrap_or_default()
+    }
+
+    /// Blocks until the server is shut down.
+    pub fn wait(mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn handle(state: &Mutex<State>, request: Request) {
+    let Ok(url) = Url::parse(&format!("http://mock{}", request.url())) else {
+        let _ = request.respond(Response::empty(400));
+        return;
+    };
+    let endpoint = url.path().rsplit('/').next().unwrap_or_default();
+    let name = endpoint.split('.').next().unwrap_or_default().to_string();
+    let params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    let param = |key: &str| {
+        params
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.to_owned())
+    };
+
+    let mut candidates = vec![name.clone()];
+    if let Some(scope) = param("Scope") {
+        candidates.insert(0, format!("{name}.{scope}"));
+        if let Some(collection) = param("DataCollection") {
+            candidates.insert(0, format!("{name}.{scope}.{collection}"));
+        }
+    }
+
+    let (fixture, fault) = {
+        let mut state = state.lock().unwrap();
+        *state.requests.entry(name.clone()).or_default() += 1;
+        let fixture = candidates
+            .iter()
+            .find_map(|candidate| state.fixtures.get(candidate))
+            .cloned();
+        (fixture, state.faults.get(&name).cloned())
+    };
+
+    if let Some(Fault::Delay(delay)) = fault {
+        std::thread::sleep(delay);
+    }
+
+    let body = match (fault, fixture) {
+        (Some(Fault::Malformed), _) => "{\"Head\": {\"Status\": ".to_string(),
+        (_, Some(fixture)) if name == "GetAPIVersion" => fixture.to_string(),
+        (Some(Fault::Status(code)), _) => envelope(&params, code, "injected fault", serde_json::json!({})),
+        (_, Some(fixture)) => envelope(&params, StatusCode::Okay, "", fixture),
+        (_, None) => envelope(&params, StatusCode::NotImplemented, "no fixture", serde_json::json!({})),
+    };
+
+    let header = Header::from_bytes("Content-Type", "application/json")
+        .expect("Content-Type header should be valid");
+    let _ = request.respond(Response::from_string(body).with_header(header));
+}
+
+/// Wraps `body` into a complete Fronius response.
+fn envelope(params: &[(String, String)], code: StatusCode, reason: &str, body: serde_json::Value) -> String {
+    let timestamp = OffsetDateTime::now_utc()
+        .format(&time::format_description::well_known::Rfc3339)
+        .expect("Current time should be formattable");
+    let arguments: HashMap<_, _> = params.iter().cloned().collect();
+    serde_json::json!({
+        "Head": {
+            "RequestArguments": arguments,
+            "Status": {
+                "Code": code,
+                "Reason": reason,
+                "UserMessage": "",
+            },
+            "Timestamp": timestamp,
+        },
+        "Body": body,
+    })
+    .to_string()
+}
@@ -0,0 +1,256 @@
+use std::time::Duration;
+
+use fronius_api::fronius::{DeviceId, Fronius};
+use rumqttc::{Client, ConnectionError, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::MqttConfig,
+    homeassistant,
+    inventory::Inventory,
+    sink::{Point, Sink, Snapshot},
+    InverterData, InverterInfo, InverterMpptData, InverterPeakData, InverterPhaseData, InverterSystemData, LoggerInfo, MeterData,
+    OhmPilotData, PowerFlowData, SensorData, StorageData, StorageHealthData, StorageModuleData, StringControlData,
+};
+
+/// Data that can be published to MQTT.
+///
+/// The data is published to `<prefix>[/<system>]/<TOPIC>[/<device id>][/<subtopic>]`.
+pub trait ToMqtt: Serialize {
+    const TOPIC: &'static str;
+
+    /// Distinguishes several points reported for the same device.
+    fn subtopic(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Capacity of the request queue of the MQTT client. Publishing only enqueues the
+/// message, so the queue has to hold a full snapshot in the `values` format and all Home
+/// Assistant discovery configs; it only runs full while the broker is unreachable.
+const REQUEST_QUEUE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadFormat {
+    /// One JSON object per poll result.
+    Json,
+    /// One topic per value, e.g. `fronius/meter/0/power`.
+    Values,
+}
+
+pub struct Publisher {
+    client: Client,
+    prefix: String,
+    format: PayloadFormat,
+    retain: bool,
+    homeassistant_prefix: Option<String>,
+}
+
+impl Publisher {
+    /// Connects to the broker in the background.
+    ///
+    /// `<prefix>/status` is set to `online` on every (re)connect and to `offline` by the
+    /// broker once the connection is lost.
+    pub fn new(config: &MqttConfig) -> Self {
+        let prefix = config.topic_prefix.to_owned();
+        let availability_topic = format!("{prefix}/status");
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(&availability_topic, "offline", QoS::AtLeastOnce, true));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+        }
+
+        let (client, mut connection) = Client::new(options, REQUEST_QUEUE_SIZE);
+        let availability_client = client.clone();
+        std::thread::spawn(move || {
+            for event in connection.iter() {
+                match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        if let Err(error) = availability_client.try_publish(&availability_topic, QoS::AtLeastOnce, true, "online") {
+                            println!("Error during mqtt publish occured: {:?}", error);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(error) => {
+                        println!("Error during mqtt connection occured: {:?}", error);
+                        // rumqttc reconnects on the next iteration, don't spin while the broker is down
+                        if !matches!(error, ConnectionError::RequestsDone) {
+                            std::thread::sleep(Duration::from_secs(5));
+                        }
+                    }
+                }
+            }
+        });
+
+        Self {
+            client,
+            prefix,
+            format: config.format,
+            retain: config.retain,
+            homeassistant_prefix: config.homeassistant.then(|| config.homeassistant_prefix.to_owned()),
+        }
+    }
+
+    pub fn format(&self) -> PayloadFormat {
+        self.format
+    }
+
+    /// The discovery prefix of Home Assistant, `None` if discovery is disabled.
+    pub fn homeassistant_prefix(&self) -> Option<&str> {
+        self.homeassistant_prefix.as_deref()
+    }
+
+    pub fn availability_topic(&self) -> String {
+        format!("{}/status", self.prefix)
+    }
+
+    /// Returns the topic data of the given type, system and device is published to.
+    pub fn topic(&self, system: Option<&str>, topic: &str, device_id: Option<&DeviceId>, subtopic: Option<&str>) -> String {
+        let mut topic = match system {
+            Some(system) => format!("{}/{system}/{topic}", self.prefix),
+            None => format!("{}/{topic}", self.prefix),
+        };
+        if let Some(device_id) = device_id {
+            topic.push_str(&format!("/{}", u8::from(device_id)));
+        }
+        if let Some(subtopic) = subtopic {
+            topic.push_str(&format!("/{subtopic}"));
+        }
+        topic
+    }
+
+    /// Publishes a retained message to `topic` as is.
+    pub fn publish_raw(&self, topic: String, payload: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
+        self.client.try_publish(topic, QoS::AtLeastOnce, true, payload)?;
+        Ok(())
+    }
+
+    pub fn publish(&self, point: &dyn Point, system: Option<&str>, device_id: Option<&DeviceId>, tags: &[(String, String)]) -> Result<(), Box<dyn std::error::Error>> {
+        let topic = self.topic(system, point.mqtt_topic(), device_id, point.mqtt_subtopic().as_deref());
+
+        match self.format {
+            PayloadFormat::Json => {
+                let mut payload = point.to_json()?;
+                if let serde_json::Value::Object(values) = &mut payload {
+                    for (key, value) in tags {
+                        values.insert(key.to_owned(), value.as_str().into());
+                    }
+                }
+                self.client.try_publish(topic, QoS::AtLeastOnce, self.retain, serde_json::to_vec(&payload)?)?;
+            }
+            PayloadFormat::Values => {
+                let serde_json::Value::Object(values) = point.to_json()? else {
+                    return Err(format!("{} is not published as object", point.mqtt_topic()).into());
+                };
+                for (name, value) in values {
+                    let payload = match value {
+                        _ if name == "time" => continue,
+                        serde_json::Value::Null => continue,
+                        serde_json::Value::String(value) => value,
+                        value => value.to_string(),
+                    };
+                    self.client.try_publish(format!("{topic}/{name}"), QoS::AtLeastOnce, self.retain, payload)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Sink for Publisher {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn write(&mut self, snapshot: &Snapshot) -> Result<(), Box<dyn std::error::Error>> {
+        let mut failed = 0;
+        for record in &snapshot.records {
+            if let Err(error) = self.publish(record.point.as_ref(), snapshot.system.as_deref(), record.device_id.as_ref(), &snapshot.tags) {
+                println!("Error during mqtt publish of {} occured: {:?}", record.point.mqtt_topic(), error);
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(format!("{failed} of {} records could not be published", snapshot.records.len()).into());
+        }
+        Ok(())
+    }
+
+    fn devices_discovered(&mut self, system: Option<&str>, fronius: &Fronius, inventory: &Inventory) -> Result<(), Box<dyn std::error::Error>> {
+        homeassistant::announce(system, fronius, inventory, self)
+    }
+}
+
+impl ToMqtt for InverterSystemData {
+    const TOPIC: &'static str = "inverter/system";
+}
+
+impl ToMqtt for InverterData {
+    const TOPIC: &'static str = "inverter";
+}
+
+impl ToMqtt for InverterMpptData {
+    const TOPIC: &'static str = "inverter_mppt";
+
+    fn subtopic(&self) -> Option<String> {
+        Some(self.mppt.to_owned())
+    }
+}
+
+impl ToMqtt for InverterPhaseData {
+    const TOPIC: &'static str = "inverter_phase";
+}
+
+impl ToMqtt for InverterPeakData {
+    const TOPIC: &'static str = "inverter_peak";
+}
+
+impl ToMqtt for InverterInfo {
+    const TOPIC: &'static str = "inverter_info";
+}
+
+impl ToMqtt for LoggerInfo {
+    const TOPIC: &'static str = "logger_info";
+}
+
+impl ToMqtt for MeterData {
+    const TOPIC: &'static str = "meter";
+}
+
+impl ToMqtt for StorageData {
+    const TOPIC: &'static str = "storage";
+}
+
+impl ToMqtt for StorageHealthData {
+    const TOPIC: &'static str = "storage_health";
+}
+
+impl ToMqtt for StorageModuleData {
+    const TOPIC: &'static str = "storage_module";
+
+    fn subtopic(&self) -> Option<String> {
+        Some(self.module.to_owned())
+    }
+}
+
+impl ToMqtt for SensorData {
+    const TOPIC: &'static str = "sensor";
+}
+
+impl ToMqtt for StringControlData {
+    const TOPIC: &'static str = "string_control";
+
+    fn subtopic(&self) -> Option<String> {
+        Some(self.string.to_owned())
+    }
+}
+
+impl ToMqtt for OhmPilotData {
+    const TOPIC: &'static str = "ohm_pilot";
+}
+
+impl ToMqtt for PowerFlowData {
+    const TOPIC: &'static str = "power_flow";
+}
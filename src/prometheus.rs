@@ -0,0 +1,487 @@
+use std::{
+    collections::BTreeMap,
+    net::ToSocketAddrs,
+    sync::{Arc, Mutex},
+};
+
+use fronius_api::fronius::DeviceId;
+use tiny_http::{Header, Response, Server};
+
+use crate::{
+    sink::{Sink, Snapshot},
+    InverterData, InverterInfo, InverterMpptData, InverterPeakData, InverterPhaseData, InverterSystemData, LoggerInfo, MeterData,
+    OhmPilotData, PowerFlowData, SensorData, StorageData, StorageHealthData, StorageModuleData, StringControlData,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    Gauge,
+    Counter,
+}
+
+impl MetricType {
+    fn name(&self) -> &'static str {
+        match self {
+            MetricType::Gauge => "gauge",
+            MetricType::Counter => "counter",
+        }
+    }
+}
+
+/// A single sample in the Prometheus text format.
+#[derive(Debug, Clone)]
+pub struct Metric {
+    name: &'static str,
+    help: &'static str,
+    metric_type: MetricType,
+    labels: Vec<(String, String)>,
+    value: f64,
+}
+
+impl Metric {
+    fn gauge(name: &'static str, help: &'static str, labels: &[(String, String)], value: Option<f64>) -> Option<Self> {
+        Self::new(name, help, MetricType::Gauge, labels, value)
+    }
+
+    fn counter(name: &'static str, help: &'static str, labels: &[(String, String)], value: Option<f64>) -> Option<Self> {
+        Self::new(name, help, MetricType::Counter, labels, value)
+    }
+
+    fn new(name: &'static str, help: &'static str, metric_type: MetricType, labels: &[(String, String)], value: Option<f64>) -> Option<Self> {
+        Some(Self {
+            name,
+            help,
+            metric_type,
+            labels: labels.to_vec(),
+            value: value?,
+        })
+    }
+}
+
+/// Data that can be exposed on the `/metrics` endpoint.
+///
+/// Values the device did not report are left out instead of being exported as `0`.
+pub trait ToMetrics {
+    fn metrics(&self) -> Vec<Metric> {
+        Vec::new()
+    }
+}
+
+/// Identifies a time series by metric name and labels.
+type SeriesKey = (&'static str, Vec<(String, String)>);
+
+/// Holds the last value of every metric, updated by the poll loop and read on scrape.
+#[derive(Debug, Default)]
+pub struct Registry {
+    /// The series of every system, keyed by the tags of the system.
+    systems: Mutex<BTreeMap<Vec<(String, String)>, BTreeMap<SeriesKey, Metric>>>,
+}
+
+impl Registry {
+    /// Replaces all series of the system identified by `tags` with the metrics of
+    /// `records`, so series of devices or values that are no longer reported disappear.
+    /// `device_id` is added as label to tell several devices of the same type apart,
+    /// `tags` are added to tell several systems apart.
+    pub fn replace(&self, tags: &[(String, String)], records: impl IntoIterator<Item = (Vec<Metric>, Option<DeviceId>)>) {
+        let mut series = BTreeMap::new();
+        for (metrics, device_id) in records {
+            for mut metric in metrics {
+                if let Some(device_id) = device_id {
+                    metric.labels.push(("device_id".to_owned(), u8::from(device_id).to_string()));
+                }
+                metric.labels.extend(tags.iter().cloned());
+                series.insert((metric.name, metric.labels.clone()), metric);
+            }
+        }
+        self.systems.lock().unwrap().insert(tags.to_vec(), series);
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let systems = self.systems.lock().unwrap();
+        // All samples of a metric have to follow its HELP and TYPE lines, also across
+        // systems
+        let metrics: BTreeMap<_, _> = systems.values().flatten().collect();
+        let mut output = String::new();
+        let mut last_name = "";
+        for metric in metrics.values() {
+            if metric.name != last_name {
+                output.push_str(&format!("# HELP {} {}\n", metric.name, metric.help));
+                output.push_str(&format!("# TYPE {} {}\n", metric.name, metric.metric_type.name()));
+                last_name = metric.name;
+            }
+            let labels: Vec<String> = metric
+                .labels
+                .iter()
+                .map(|(name, value)| format!("{name}=\"{}\"", label_value(value)))
+                .collect();
+            output.push_str(&format!("{}{{{}}} {}\n", metric.name, labels.join(","), sample_value(metric.value)));
+        }
+        output
+    }
+}
+
+fn label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn sample_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_owned()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_owned()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Serves the last polled values on `http://<addr>/metrics`.
+pub struct PrometheusSink {
+    registry: Arc<Registry>,
+}
+
+impl PrometheusSink {
+    /// Starts serving the metrics from a background thread.
+    pub fn serve(addr: impl ToSocketAddrs) -> Result<Self, Box<dyn std::error::Error>> {
+        let server = Server::http(addr).map_err(|error| error.to_string())?;
+        let registry = Arc::new(Registry::default());
+        let scrape_registry = registry.clone();
+        std::thread::spawn(move || {
+            let content_type = Header::from_bytes("Content-Type", "text/plain; version=0.0.4")
+                .expect("Content-Type header should be valid");
+            for request in server.incoming_requests() {
+                let response = if request.url() == "/metrics" {
+                    Response::from_string(scrape_registry.render()).with_header(content_type.clone())
+                } else {
+                    Response::from_string("Not Found").with_status_code(404)
+                };
+                if let Err(error) = request.respond(response) {
+                    println!("Error during metrics scrape occured: {:?}", error);
+                }
+            }
+        });
+        Ok(Self { registry })
+    }
+}
+
+impl Sink for PrometheusSink {
+    fn name(&self) -> &'static str {
+        "prometheus"
+    }
+
+    fn write(&mut self, snapshot: &Snapshot) -> Result<(), Box<dyn std::error::Error>> {
+        let records = snapshot.records.iter().map(|record| (record.point.to_metrics(), record.device_id));
+        self.registry.replace(&snapshot.tags, records);
+        Ok(())
+    }
+}
+
+fn device_labels(device: &str) -> Vec<(String, String)> {
+    vec![("device".to_owned(), device.to_owned())]
+}
+
+impl ToMetrics for InverterData {
+    fn metrics(&self) -> Vec<Metric> {
+        let mut labels = device_labels(&self.device);
+        labels.push(("serial".to_owned(), self.serial.as_str().to_owned()));
+        [
+            Metric::gauge("fronius_inverter_ac_power_watts", "AC power of the inverter", &labels, self.ac_power),
+            Metric::gauge("fronius_inverter_ac_apparent_power_voltamperes", "AC apparent power of the inverter", &labels, self.ac_power_abs),
+            Metric::gauge("fronius_inverter_ac_current_amperes", "AC current of the inverter", &labels, self.ac_current),
+            Metric::gauge("fronius_inverter_ac_voltage_volts", "AC voltage of the inverter", &labels, self.ac_voltage),
+            Metric::gauge("fronius_inverter_ac_frequency_hertz", "AC frequency of the inverter", &labels, self.ac_frequency),
+            Metric::gauge("fronius_inverter_dc_current_amperes", "DC current of the inverter", &labels, self.dc_current),
+            Metric::gauge("fronius_inverter_dc_voltage_volts", "DC voltage of the inverter", &labels, self.dc_voltage),
+            Metric::counter("fronius_inverter_energy_watthours_total", "Energy produced by the inverter since installation", &labels, self.total_energy),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl ToMetrics for InverterMpptData {
+    fn metrics(&self) -> Vec<Metric> {
+        let mut labels = device_labels(&self.device);
+        labels.push(("serial".to_owned(), self.serial.as_str().to_owned()));
+        labels.push(("mppt".to_owned(), self.mppt.to_owned()));
+        [
+            Metric::gauge("fronius_inverter_mppt_dc_current_amperes", "DC current of the inverter per MPP tracker", &labels, self.dc_current),
+            Metric::gauge("fronius_inverter_mppt_dc_voltage_volts", "DC voltage of the inverter per MPP tracker", &labels, self.dc_voltage),
+            Metric::gauge("fronius_inverter_mppt_dc_power_watts", "DC power of the inverter per MPP tracker", &labels, self.dc_power),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl ToMetrics for InverterPhaseData {
+    fn metrics(&self) -> Vec<Metric> {
+        let mut labels = device_labels(&self.device);
+        labels.push(("serial".to_owned(), self.serial.as_str().to_owned()));
+        let phase = |phase: &str| {
+            let mut labels = labels.clone();
+            labels.push(("phase".to_owned(), phase.to_owned()));
+            labels
+        };
+        let fan = |fan: &str| {
+            let mut labels = labels.clone();
+            labels.push(("fan".to_owned(), fan.to_owned()));
+            labels
+        };
+        [
+            Metric::gauge("fronius_inverter_phase_ac_current_amperes", "AC current of the inverter per phase", &phase("L1"), self.ac_l1_current),
+            Metric::gauge("fronius_inverter_phase_ac_current_amperes", "AC current of the inverter per phase", &phase("L2"), self.ac_l2_current),
+            Metric::gauge("fronius_inverter_phase_ac_current_amperes", "AC current of the inverter per phase", &phase("L3"), self.ac_l3_current),
+            Metric::gauge("fronius_inverter_phase_ac_voltage_volts", "AC voltage of the inverter per phase", &phase("L1"), self.ac_l1_voltage),
+            Metric::gauge("fronius_inverter_phase_ac_voltage_volts", "AC voltage of the inverter per phase", &phase("L2"), self.ac_l2_voltage),
+            Metric::gauge("fronius_inverter_phase_ac_voltage_volts", "AC voltage of the inverter per phase", &phase("L3"), self.ac_l3_voltage),
+            Metric::gauge("fronius_inverter_phase_ac_apparent_power_voltamperes", "AC apparent power of the inverter per phase", &phase("L1"), self.ac_l1_apparent_power),
+            Metric::gauge("fronius_inverter_phase_ac_apparent_power_voltamperes", "AC apparent power of the inverter per phase", &phase("L2"), self.ac_l2_apparent_power),
+            Metric::gauge("fronius_inverter_phase_ac_apparent_power_voltamperes", "AC apparent power of the inverter per phase", &phase("L3"), self.ac_l3_apparent_power),
+            Metric::gauge("fronius_inverter_ambient_temperature_celsius", "Ambient temperature measured by the inverter", &labels, self.ambient_temperature),
+            Metric::gauge("fronius_inverter_fan_speed_rpm", "Rotation speed of the inverter fans", &fan("front_left"), self.fan_front_left_speed),
+            Metric::gauge("fronius_inverter_fan_speed_rpm", "Rotation speed of the inverter fans", &fan("front_right"), self.fan_front_right_speed),
+            Metric::gauge("fronius_inverter_fan_speed_rpm", "Rotation speed of the inverter fans", &fan("back_left"), self.fan_back_left_speed),
+            Metric::gauge("fronius_inverter_fan_speed_rpm", "Rotation speed of the inverter fans", &fan("back_right"), self.fan_back_right_speed),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl ToMetrics for InverterSystemData {
+    fn metrics(&self) -> Vec<Metric> {
+        let labels = device_labels(&self.device);
+        [
+            Metric::gauge("fronius_system_ac_power_watts", "AC power of all inverters of the system", &labels, self.ac_power),
+            Metric::counter("fronius_system_energy_watthours_total", "Energy produced by all inverters of the system since installation", &labels, self.total_energy),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl ToMetrics for MeterData {
+    fn metrics(&self) -> Vec<Metric> {
+        let mut labels = device_labels(&self.device);
+        labels.push(("serial".to_owned(), self.serial.as_str().to_owned()));
+        let phase = |phase: &str| {
+            let mut labels = labels.clone();
+            labels.push(("phase".to_owned(), phase.to_owned()));
+            labels
+        };
+        [
+            Metric::gauge("fronius_meter_current_amperes", "Current per phase measured by the meter", &phase("L1"), self.l1_current),
+            Metric::gauge("fronius_meter_current_amperes", "Current per phase measured by the meter", &phase("L2"), self.l2_current),
+            Metric::gauge("fronius_meter_current_amperes", "Current per phase measured by the meter", &phase("L3"), self.l3_current),
+            Metric::gauge("fronius_meter_voltage_volts", "Voltage per phase measured by the meter", &phase("L1"), self.l1_voltage),
+            Metric::gauge("fronius_meter_voltage_volts", "Voltage per phase measured by the meter", &phase("L2"), self.l2_voltage),
+            Metric::gauge("fronius_meter_voltage_volts", "Voltage per phase measured by the meter", &phase("L3"), self.l3_voltage),
+            Metric::gauge("fronius_meter_phase_power_watts", "Real power per phase measured by the meter", &phase("L1"), self.l1_power),
+            Metric::gauge("fronius_meter_phase_power_watts", "Real power per phase measured by the meter", &phase("L2"), self.l2_power),
+            Metric::gauge("fronius_meter_phase_power_watts", "Real power per phase measured by the meter", &phase("L3"), self.l3_power),
+            Metric::gauge("fronius_meter_power_watts", "Real power measured by the meter", &labels, Some(self.power)),
+            Metric::gauge("fronius_meter_frequency_hertz", "Average grid frequency measured by the meter", &labels, Some(self.frequency_average)),
+            Metric::gauge("fronius_meter_phase_apparent_power_voltamperes", "Apparent power per phase measured by the meter", &phase("L1"), self.l1_apparent_power),
+            Metric::gauge("fronius_meter_phase_apparent_power_voltamperes", "Apparent power per phase measured by the meter", &phase("L2"), self.l2_apparent_power),
+            Metric::gauge("fronius_meter_phase_apparent_power_voltamperes", "Apparent power per phase measured by the meter", &phase("L3"), self.l3_apparent_power),
+            Metric::gauge("fronius_meter_apparent_power_voltamperes", "Apparent power measured by the meter", &labels, Some(self.apparent_power)),
+            Metric::gauge("fronius_meter_phase_reactive_power_var", "Reactive power per phase measured by the meter", &phase("L1"), self.l1_reactive_power),
+            Metric::gauge("fronius_meter_phase_reactive_power_var", "Reactive power per phase measured by the meter", &phase("L2"), self.l2_reactive_power),
+            Metric::gauge("fronius_meter_phase_reactive_power_var", "Reactive power per phase measured by the meter", &phase("L3"), self.l3_reactive_power),
+            Metric::gauge("fronius_meter_reactive_power_var", "Reactive power measured by the meter", &labels, Some(self.reactive_power)),
+            Metric::gauge("fronius_meter_phase_power_factor", "Power factor per phase measured by the meter", &phase("L1"), self.l1_power_factor),
+            Metric::gauge("fronius_meter_phase_power_factor", "Power factor per phase measured by the meter", &phase("L2"), self.l2_power_factor),
+            Metric::gauge("fronius_meter_phase_power_factor", "Power factor per phase measured by the meter", &phase("L3"), self.l3_power_factor),
+            Metric::gauge("fronius_meter_power_factor", "Power factor measured by the meter", &labels, Some(self.power_factor)),
+            Metric::counter("fronius_meter_energy_consumed_watthours_total", "Real energy consumed, i.e. imported from the grid by a feed-in meter", &labels, Some(self.energy_consumed)),
+            Metric::counter("fronius_meter_energy_produced_watthours_total", "Real energy produced, i.e. exported to the grid by a feed-in meter", &labels, Some(self.energy_produced)),
+            Metric::counter("fronius_meter_phase_energy_consumed_watthours_total", "Real energy consumed per phase", &phase("L1"), self.l1_energy_consumed),
+            Metric::counter("fronius_meter_phase_energy_consumed_watthours_total", "Real energy consumed per phase", &phase("L2"), self.l2_energy_consumed),
+            Metric::counter("fronius_meter_phase_energy_consumed_watthours_total", "Real energy consumed per phase", &phase("L3"), self.l3_energy_consumed),
+            Metric::counter("fronius_meter_phase_energy_produced_watthours_total", "Real energy produced per phase", &phase("L1"), self.l1_energy_produced),
+            Metric::counter("fronius_meter_phase_energy_produced_watthours_total", "Real energy produced per phase", &phase("L2"), self.l2_energy_produced),
+            Metric::counter("fronius_meter_phase_energy_produced_watthours_total", "Real energy produced per phase", &phase("L3"), self.l3_energy_produced),
+            Metric::counter("fronius_meter_reactive_energy_consumed_varhours_total", "Reactive energy consumed", &labels, self.reactive_energy_consumed),
+            Metric::counter("fronius_meter_reactive_energy_produced_varhours_total", "Reactive energy produced", &labels, self.reactive_energy_produced),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl ToMetrics for StorageData {
+    fn metrics(&self) -> Vec<Metric> {
+        let mut labels = device_labels(&self.device);
+        labels.push(("serial".to_owned(), self.serial.as_str().to_owned()));
+        [
+            Metric::gauge("fronius_storage_enabled", "Whether the storage is enabled", &labels, Some(f64::from(u8::from(self.enabled)))),
+            Metric::gauge("fronius_storage_charge_ratio", "State of charge of the storage", &labels, Some(self.charge_percentage / 100.0)),
+            Metric::gauge("fronius_storage_capacity_watthours", "Maximum capacity of the storage", &labels, Some(self.capacity)),
+            Metric::gauge("fronius_storage_dc_current_amperes", "DC current of the storage", &labels, Some(self.dc_current)),
+            Metric::gauge("fronius_storage_dc_voltage_volts", "DC voltage of the storage", &labels, Some(self.dc_voltage)),
+            Metric::gauge("fronius_storage_cell_temperature_celsius", "Cell temperature of the storage", &labels, Some(self.temperature_cell)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl ToMetrics for StorageHealthData {
+    fn metrics(&self) -> Vec<Metric> {
+        let mut labels = device_labels(&self.device);
+        labels.push(("serial".to_owned(), self.serial.as_str().to_owned()));
+        [
+            Metric::gauge("fronius_storage_designed_capacity_watthours", "Designed capacity of the storage", &labels, self.designed_capacity),
+            Metric::gauge("fronius_storage_state_of_health_ratio", "Maximum capacity of the storage relative to its designed capacity", &labels, self.state_of_health.map(|value| value / 100.0)),
+            Metric::counter("fronius_storage_cycles_total", "Charge cycles reported by the battery cells", &labels, self.cycle_count),
+            Metric::counter("fronius_storage_charged_energy_watthours_total", "DC energy charged into the storage", &labels, Some(self.charged_energy)),
+            Metric::counter("fronius_storage_discharged_energy_watthours_total", "DC energy discharged from the storage", &labels, Some(self.discharged_energy)),
+            Metric::counter("fronius_storage_equivalent_full_cycles_total", "Equivalent full cycles of the storage relative to its designed capacity", &labels, self.equivalent_full_cycles),
+            Metric::gauge("fronius_storage_day_round_trip_efficiency_ratio", "Energy discharged today relative to the energy charged today", &labels, self.day_round_trip_efficiency.map(|value| value / 100.0)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl ToMetrics for StorageModuleData {
+    fn metrics(&self) -> Vec<Metric> {
+        let mut labels = device_labels(&self.device);
+        labels.push(("module".to_owned(), self.module.to_owned()));
+        labels.push(("serial".to_owned(), self.serial.as_str().to_owned()));
+        [
+            Metric::gauge("fronius_storage_module_enabled", "Whether the battery module is enabled", &labels, self.enabled.map(|enabled| f64::from(u8::from(enabled)))),
+            Metric::gauge("fronius_storage_module_charge_ratio", "State of charge of the battery module", &labels, self.charge_percentage.map(|value| value / 100.0)),
+            Metric::gauge("fronius_storage_module_capacity_watthours", "Maximum capacity of the battery module", &labels, self.capacity),
+            Metric::gauge("fronius_storage_module_designed_capacity_watthours", "Designed capacity of the battery module", &labels, self.designed_capacity),
+            Metric::counter("fronius_storage_module_cycles_total", "Charge cycles of the battery module cells", &labels, self.cycle_count),
+            Metric::gauge("fronius_storage_module_dc_current_amperes", "DC current of the battery module", &labels, self.dc_current),
+            Metric::gauge("fronius_storage_module_dc_voltage_volts", "DC voltage of the battery module", &labels, self.dc_voltage),
+            Metric::gauge("fronius_storage_module_cell_voltage_max_volts", "Highest cell voltage of the battery module", &labels, self.cell_voltage_max),
+            Metric::gauge("fronius_storage_module_cell_voltage_min_volts", "Lowest cell voltage of the battery module", &labels, self.cell_voltage_min),
+            Metric::gauge("fronius_storage_module_cell_voltage_spread_volts", "Difference between the highest and lowest cell voltage", &labels, self.cell_voltage_spread),
+            Metric::gauge("fronius_storage_module_cell_temperature_celsius", "Cell temperature of the battery module", &labels, self.temperature_cell),
+            Metric::gauge("fronius_storage_module_cell_temperature_max_celsius", "Highest cell temperature of the battery module", &labels, self.temperature_cell_max),
+            Metric::gauge("fronius_storage_module_cell_temperature_min_celsius", "Lowest cell temperature of the battery module", &labels, self.temperature_cell_min),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl ToMetrics for OhmPilotData {
+    fn metrics(&self) -> Vec<Metric> {
+        let mut labels = device_labels(&self.device);
+        labels.push(("serial".to_owned(), self.serial.as_str().to_owned()));
+        [
+            Metric::gauge("fronius_ohm_pilot_power_watts", "Power consumed by the Ohmpilot", &labels, Some(self.power)),
+            Metric::gauge("fronius_ohm_pilot_temperature_celsius", "Temperature measured by the Ohmpilot", &labels, Some(self.temperature)),
+            Metric::gauge("fronius_ohm_pilot_error_code", "Error code of the Ohmpilot, 0 if there is no error", &labels, Some(self.error_code as f64)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl ToMetrics for PowerFlowData {
+    fn metrics(&self) -> Vec<Metric> {
+        let labels = device_labels(&self.device);
+        [
+            Metric::gauge("fronius_power_flow_akku_watts", "Power flowing from the battery, negative when charging", &labels, self.akku),
+            Metric::gauge("fronius_power_flow_grid_watts", "Power flowing from the grid, negative when feeding in", &labels, self.grid),
+            Metric::gauge("fronius_power_flow_load_watts", "Power consumed by the load, negative when consuming", &labels, self.load),
+            Metric::gauge("fronius_power_flow_photovoltaik_watts", "Power produced by the photovoltaic system", &labels, Some(self.photovoltaik)),
+            Metric::gauge("fronius_power_flow_relative_autonomy_ratio", "Share of the load covered by the system", &labels, self.relative_autonomy.map(|value| value / 100.0)),
+            Metric::gauge("fronius_power_flow_relative_self_consumption_ratio", "Share of the production consumed locally", &labels, self.relative_self_consumption.map(|value| value / 100.0)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl ToMetrics for InverterPeakData {}
+impl ToMetrics for InverterInfo {}
+impl ToMetrics for LoggerInfo {}
+impl ToMetrics for SensorData {}
+impl ToMetrics for StringControlData {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system(name: &str) -> Vec<(String, String)> {
+        vec![("system".to_owned(), name.to_owned())]
+    }
+
+    fn power(device: &str, value: f64) -> Vec<Metric> {
+        Metric::gauge("fronius_meter_power_watts", "Real power measured by the meter", &device_labels(device), Some(value))
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn render_groups_samples_by_metric() {
+        let registry = Registry::default();
+        let meter = DeviceId::try_from(0u8).unwrap();
+        let metrics = [
+            Metric::counter("fronius_meter_energy_consumed_watthours_total", "Real energy consumed", &device_labels("Meter"), Some(1500.0)),
+            Metric::gauge("fronius_meter_power_watts", "Real power measured by the meter", &device_labels("Meter"), Some(-230.5)),
+            Metric::gauge("fronius_meter_frequency_hertz", "Average grid frequency", &device_labels("Meter"), None),
+        ];
+        registry.replace(&system("home"), [(metrics.into_iter().flatten().collect(), Some(meter))]);
+        registry.replace(&system("garage"), [(power("Meter", 42.0), Some(meter))]);
+
+        assert_eq!(
+            registry.render(),
+            "# HELP fronius_meter_energy_consumed_watthours_total Real energy consumed\n\
+             # TYPE fronius_meter_energy_consumed_watthours_total counter\n\
+             fronius_meter_energy_consumed_watthours_total{device=\"Meter\",device_id=\"0\",system=\"home\"} 1500\n\
+             # HELP fronius_meter_power_watts Real power measured by the meter\n\
+             # TYPE fronius_meter_power_watts gauge\n\
+             fronius_meter_power_watts{device=\"Meter\",device_id=\"0\",system=\"garage\"} 42\n\
+             fronius_meter_power_watts{device=\"Meter\",device_id=\"0\",system=\"home\"} -230.5\n"
+        );
+    }
+
+    #[test]
+    fn render_escapes_label_values() {
+        let registry = Registry::default();
+        registry.replace(&system("roof \"west\"\\\n"), [(power("Meter", 1.0), None)]);
+        assert!(registry.render().contains("system=\"roof \\\"west\\\"\\\\\\n\"} 1\n"));
+    }
+
+    #[test]
+    fn render_special_values() {
+        assert_eq!(sample_value(f64::NAN), "NaN");
+        assert_eq!(sample_value(f64::INFINITY), "+Inf");
+        assert_eq!(sample_value(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(sample_value(0.25), "0.25");
+    }
+
+    #[test]
+    fn replace_drops_series_of_disappeared_devices() {
+        let registry = Registry::default();
+        let first = DeviceId::try_from(0u8).unwrap();
+        let second = DeviceId::try_from(1u8).unwrap();
+        registry.replace(&system("home"), [(power("Meter", 1.0), Some(first)), (power("Meter", 2.0), Some(second))]);
+        registry.replace(&system("garage"), [(power("Meter", 3.0), Some(first))]);
+        registry.replace(&system("home"), [(power("Meter", 4.0), Some(first))]);
+
+        let output = registry.render();
+        assert!(output.contains("fronius_meter_power_watts{device=\"Meter\",device_id=\"0\",system=\"home\"} 4\n"));
+        assert!(!output.contains("device_id=\"1\""));
+        // Other systems keep their series
+        assert!(output.contains("fronius_meter_power_watts{device=\"Meter\",device_id=\"0\",system=\"garage\"} 3\n"));
+    }
+}
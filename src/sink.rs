@@ -0,0 +1,162 @@
+use fronius_api::fronius::{self, DeviceId, Fronius};
+
+use crate::{
+    config::{Config, Endpoint},
+    influx::InfluxSink,
+    inventory::Inventory,
+    mqtt::{Publisher, ToMqtt},
+    prometheus::{Metric, PrometheusSink, ToMetrics},
+};
+
+/// A single poll result, as written to the sinks.
+///
+/// Implemented for every type that can be written to all outputs.
+pub trait Point: Send + Sync {
+    fn write_line_protocol(&self, w: &mut Vec<u8>) -> std::io::Result<()>;
+    fn to_metrics(&self) -> Vec<Metric>;
+    fn mqtt_topic(&self) -> &'static str;
+    fn mqtt_subtopic(&self) -> Option<String>;
+    fn to_json(&self) -> serde_json::Result<serde_json::Value>;
+}
+
+impl<T> Point for T
+where
+    T: influxdb2::models::WriteDataPoint + ToMetrics + ToMqtt + Send + Sync,
+{
+    fn write_line_protocol(&self, w: &mut Vec<u8>) -> std::io::Result<()> {
+        self.write_data_point_to(w)
+    }
+
+    fn to_metrics(&self) -> Vec<Metric> {
+        self.metrics()
+    }
+
+    fn mqtt_topic(&self) -> &'static str {
+        T::TOPIC
+    }
+
+    fn mqtt_subtopic(&self) -> Option<String> {
+        self.subtopic()
+    }
+
+    fn to_json(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(self)
+    }
+}
+
+pub struct Record {
+    /// The device the point was read from, `None` for system-wide data.
+    pub device_id: Option<DeviceId>,
+    pub point: Box<dyn Point>,
+}
+
+/// All data read from one system in one iteration of the poll loop.
+#[derive(Default)]
+pub struct Snapshot {
+    /// The name of the system, if configured.
+    pub system: Option<String>,
+    /// Tags added to every point, including the `system` tag.
+    pub tags: Vec<(String, String)>,
+    pub records: Vec<Record>,
+    /// Endpoints the device reported as not implemented or not supported.
+    pub unsupported: Vec<Endpoint>,
+}
+
+impl Snapshot {
+    pub fn push<T: Point + 'static>(&mut self, endpoint: Endpoint, name: &str, device_id: Option<&DeviceId>, data: Result<T, Box<dyn std::error::Error>>) {
+        self.push_all(endpoint, name, device_id, data.map(|val| vec![val]));
+    }
+
+    pub fn push_all<T: Point + 'static>(&mut self, endpoint: Endpoint, name: &str, device_id: Option<&DeviceId>, data: Result<Vec<T>, Box<dyn std::error::Error>>) {
+        match data {
+            Ok(val) => self.records.extend(val.into_iter().map(|point| Record {
+                device_id: device_id.copied(),
+                point: Box::new(point),
+            })),
+            Err(error) => {
+                println!("Error during fetch of {name} occured: {:?}", error);
+                let unsupported = error
+                    .downcast_ref::<fronius::Error>()
+                    .is_some_and(fronius::Error::is_unsupported);
+                if unsupported && !self.unsupported.contains(&endpoint) {
+                    self.unsupported.push(endpoint);
+                }
+            }
+        }
+    }
+}
+
+/// An output the polled data is written to.
+pub trait Sink {
+    fn name(&self) -> &'static str;
+
+    fn write(&mut self, snapshot: &Snapshot) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Called after connecting to a system and whenever its list of connected devices
+    /// was refreshed.
+    fn devices_discovered(&mut self, _system: Option<&str>, _fronius: &Fronius, _inventory: &Inventory) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+}
+
+/// Creates all sinks enabled in the config.
+pub fn from_config(config: &Config) -> Result<Vec<Box<dyn Sink>>, Box<dyn std::error::Error>> {
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    if let Some(influxdb) = &config.influxdb {
+        sinks.push(Box::new(InfluxSink::new(influxdb)?));
+    }
+    if let Some(prometheus) = &config.prometheus {
+        sinks.push(Box::new(PrometheusSink::serve(&prometheus.addr)?));
+        println!("Serving metrics on http://{}/metrics", prometheus.addr);
+    }
+    if let Some(mqtt) = &config.mqtt {
+        sinks.push(Box::new(Publisher::new(mqtt)));
+    }
+    if sinks.is_empty() {
+        return Err("No output configured, configure influxdb, prometheus or mqtt".into());
+    }
+    Ok(sinks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MeterData, PowerFlowData};
+
+    fn unsupported() -> Box<dyn std::error::Error> {
+        fronius::Error::UnsupportedApiVersion(0).into()
+    }
+
+    #[test]
+    fn push_all_records_every_point() {
+        let meter = DeviceId::try_from(1u8).unwrap();
+        let mut snapshot = Snapshot::default();
+        snapshot.push_all(Endpoint::Meter, "meter_data", Some(&meter), Ok(vec![MeterData::default(), MeterData::default()]));
+        snapshot.push(Endpoint::PowerFlow, "power_flow_data", None, Ok(PowerFlowData::default()));
+
+        let records: Vec<_> = snapshot.records.iter().map(|record| (record.point.mqtt_topic(), record.device_id)).collect();
+        assert_eq!(records, [("meter", Some(meter)), ("meter", Some(meter)), ("power_flow", None)]);
+        assert!(snapshot.unsupported.is_empty());
+    }
+
+    #[test]
+    fn push_all_records_unsupported_endpoint_once() {
+        let mut snapshot = Snapshot::default();
+        snapshot.push_all::<MeterData>(Endpoint::Meter, "meter_data", None, Err(unsupported()));
+        snapshot.push::<MeterData>(Endpoint::Meter, "meter_data", None, Err(unsupported()));
+        snapshot.push::<PowerFlowData>(Endpoint::PowerFlow, "power_flow_data", None, Err(unsupported()));
+
+        assert!(snapshot.records.is_empty());
+        assert_eq!(snapshot.unsupported, [Endpoint::Meter, Endpoint::PowerFlow]);
+    }
+
+    #[test]
+    fn push_all_ignores_other_errors() {
+        let mut snapshot = Snapshot::default();
+        snapshot.push_all::<MeterData>(Endpoint::Meter, "meter_data", None, Err(fronius::Error::InvalidEndpoint("meter".to_owned()).into()));
+        snapshot.push::<MeterData>(Endpoint::Meter, "meter_data", None, Err("connection refused".into()));
+
+        assert!(snapshot.records.is_empty());
+        assert!(snapshot.unsupported.is_empty());
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{Local, NaiveDate, TimeZone};
+use fronius_api::fronius::DeviceId;
+use influxdb2::models::Query;
+use influxdb2_structmap::value::Value;
+use time::OffsetDateTime;
+
+use crate::influx::{flux_string, flux_tag_filters, InfluxSink};
+
+/// Longest gap in seconds between two samples that is still integrated, unless the poll
+/// interval asks for a longer one. Longer gaps, e.g. while the device was unreachable, are
+/// skipped instead of being interpolated.
+const MIN_SAMPLE_GAP: i64 = 10 * 60;
+
+/// Polls a gap may span before it is skipped, so a single failed poll is still integrated.
+const SAMPLE_GAP_POLLS: i64 = 3;
+
+/// Days the last written counters are looked up in InfluxDB on startup.
+const SEED_RANGE_DAYS: i64 = 365;
+
+/// Energy that flowed into and out of a battery, in Wh.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Throughput {
+    /// Energy charged since the counters were started.
+    pub charged_energy: f64,
+    /// Energy discharged since the counters were started.
+    pub discharged_energy: f64,
+    /// Energy charged since local midnight.
+    pub day_charged_energy: f64,
+    /// Energy discharged since local midnight.
+    pub day_discharged_energy: f64,
+}
+
+#[derive(Debug, Default)]
+struct Battery {
+    /// Unix timestamp and DC power of the last sample.
+    last_sample: Option<(i64, f64)>,
+    day: Option<NaiveDate>,
+    throughput: Throughput,
+}
+
+/// Integrates the DC power of every battery of a system over the poll loop.
+#[derive(Debug)]
+pub struct StorageHealth {
+    /// Longest gap in seconds between two samples that is still integrated.
+    max_sample_gap: i64,
+    batteries: HashMap<DeviceId, Battery>,
+}
+
+fn local_day(timestamp: i64) -> Option<NaiveDate> {
+    Local.timestamp_opt(timestamp, 0).single().map(|time| time.date_naive())
+}
+
+impl StorageHealth {
+    /// Creates the counters of a system polled every `poll_interval`.
+    pub fn new(poll_interval: Duration) -> Self {
+        let poll_interval = i64::try_from(poll_interval.as_secs()).unwrap_or(i64::MAX);
+        Self {
+            max_sample_gap: std::cmp::max(MIN_SAMPLE_GAP, poll_interval.saturating_mul(SAMPLE_GAP_POLLS)),
+            batteries: HashMap::new(),
+        }
+    }
+
+    /// Adds the energy since the last sample of the battery and returns its throughput.
+    /// `power` is positive while discharging and negative while charging, like `P_Akku`
+    /// of the power flow.
+    ///
+    /// Samples that are not newer than the previous one, i.e. not yet updated data, are
+    /// ignored.
+    pub fn record(&mut self, device_id: DeviceId, time: OffsetDateTime, power: f64) -> Throughput {
+        let battery = self.batteries.entry(device_id).or_default();
+        let timestamp = time.unix_timestamp();
+        if battery.last_sample.is_some_and(|(last_timestamp, _)| timestamp <= last_timestamp) {
+            return battery.throughput;
+        }
+
+        let day = local_day(timestamp);
+        if battery.day != day {
+            battery.day = day;
+            battery.throughput.day_charged_energy = 0.0;
+            battery.throughput.day_discharged_energy = 0.0;
+        }
+
+        match battery.last_sample {
+            Some((last_timestamp, last_power)) if timestamp - last_timestamp <= self.max_sample_gap => {
+                let seconds = (timestamp - last_timestamp) as f64;
+                // Trapezoidal rule, a change of direction between the samples is attributed
+                // to the direction of the average power
+                let energy = (last_power + power) / 2.0 * seconds / 3600.0;
+                if energy > 0.0 {
+                    battery.throughput.discharged_energy += energy;
+                    battery.throughput.day_discharged_energy += energy;
+                } else {
+                    battery.throughput.charged_energy -= energy;
+                    battery.throughput.day_charged_energy -= energy;
+                }
+            }
+            _ => {}
+        }
+        battery.last_sample = Some((timestamp, power));
+        battery.throughput
+    }
+
+    /// Continues the counters of a battery with the values written at `time`, before the
+    /// exporter was restarted. The day counters are reset by the first sample of a later
+    /// day.
+    pub fn seed(&mut self, device_id: DeviceId, time: OffsetDateTime, throughput: Throughput) {
+        let battery = self.batteries.entry(device_id).or_default();
+        battery.day = local_day(time.unix_timestamp());
+        battery.throughput = throughput;
+    }
+
+    /// Seeds the counters of every battery of the system with the given tags from the last
+    /// `storage_health` point written to InfluxDB, so they don't start at 0 on every
+    /// restart.
+    pub fn seed_from_influx(&mut self, influx: &InfluxSink, tags: &[(String, String)]) -> Result<(), Box<dyn std::error::Error>> {
+        let query = Query::new(format!(
+            r#"from(bucket: {})
+            |> range(start: -{SEED_RANGE_DAYS}d)
+            |> filter(fn: (r) => r._measurement == "storage_health"){}
+            |> filter(fn: (r) => r._field == "charged_energy" or r._field == "discharged_energy" or r._field == "day_charged_energy" or r._field == "day_discharged_energy")
+            |> group(columns: ["device_id", "_field"])
+            |> last()"#,
+            flux_string(influx.bucket()),
+            flux_tag_filters(tags),
+        ));
+        let records = influx.runtime().block_on(influx.client().query_raw(Some(query)))?;
+
+        let mut seeds: HashMap<DeviceId, (OffsetDateTime, Throughput)> = HashMap::new();
+        for record in records {
+            let values = &record.values;
+            let (Some(Value::String(device_id)), Some(Value::String(field)), Some(Value::Double(value)), Some(Value::TimeRFC(time))) =
+                (values.get("device_id"), values.get("_field"), values.get("_value"), values.get("_time"))
+            else {
+                continue;
+            };
+            let Some(device_id) = device_id.parse::<u8>().ok().and_then(|device_id| DeviceId::try_from(device_id).ok()) else {
+                continue;
+            };
+            let Ok(time) = OffsetDateTime::from_unix_timestamp(time.timestamp()) else {
+                continue;
+            };
+            let (last, throughput) = seeds.entry(device_id).or_insert((time, Throughput::default()));
+            *last = std::cmp::max(*last, time);
+            match field.as_str() {
+                "charged_energy" => throughput.charged_energy = value.into_inner(),
+                "discharged_energy" => throughput.discharged_energy = value.into_inner(),
+                "day_charged_energy" => throughput.day_charged_energy = value.into_inner(),
+                "day_discharged_energy" => throughput.day_discharged_energy = value.into_inner(),
+                _ => {}
+            }
+        }
+        for (device_id, (time, throughput)) in seeds {
+            self.seed(device_id, time, throughput);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn battery(id: u8) -> DeviceId {
+        DeviceId::try_from(id).unwrap()
+    }
+
+    /// Unix timestamp of the given local time.
+    fn local(day: u32, hour: u32, min: u32) -> i64 {
+        Local.with_ymd_and_hms(2024, 5, day, hour, min, 0).single().unwrap().timestamp()
+    }
+
+    fn at(timestamp: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(timestamp).unwrap()
+    }
+
+    #[test]
+    fn integrates_charging_and_discharging() {
+        let mut health = StorageHealth::new(Duration::from_secs(5));
+        let start = local(2, 12, 0);
+        // The first sample only sets the start of the integration
+        assert_eq!(health.record(battery(0), at(start), -3600.0), Throughput::default());
+
+        let throughput = health.record(battery(0), at(start + 60), -3600.0);
+        assert_eq!(throughput.charged_energy, 60.0);
+        assert_eq!(throughput.day_charged_energy, 60.0);
+        assert_eq!(throughput.discharged_energy, 0.0);
+
+        health.record(battery(0), at(start + 120), 7200.0);
+        let throughput = health.record(battery(0), at(start + 180), 7200.0);
+        assert_eq!(throughput.charged_energy, 60.0);
+        // 30 Wh for the minute the direction changed, the average power was discharging
+        assert_eq!(throughput.discharged_energy, 30.0 + 120.0);
+        assert_eq!(throughput.day_discharged_energy, 150.0);
+    }
+
+    #[test]
+    fn direction_change_is_attributed_to_average_power() {
+        let mut health = StorageHealth::new(Duration::from_secs(5));
+        let start = local(2, 12, 0);
+        health.record(battery(0), at(start), 1800.0);
+        let throughput = health.record(battery(0), at(start + 60), -5400.0);
+        assert_eq!(throughput.charged_energy, 30.0);
+        assert_eq!(throughput.discharged_energy, 0.0);
+    }
+
+    #[test]
+    fn gaps_longer_than_ten_minutes_are_skipped() {
+        let mut health = StorageHealth::new(Duration::from_secs(5));
+        let start = local(2, 12, 0);
+        health.record(battery(0), at(start), -3600.0);
+        assert_eq!(health.record(battery(0), at(start + MIN_SAMPLE_GAP + 1), -3600.0).charged_energy, 0.0);
+        // Integration continues from the sample after the gap
+        assert_eq!(health.record(battery(0), at(start + MIN_SAMPLE_GAP + 61), -3600.0).charged_energy, 60.0);
+        // A gap of exactly ten minutes is still integrated
+        assert_eq!(health.record(battery(0), at(start + 2 * MIN_SAMPLE_GAP + 61), -3600.0).charged_energy, 660.0);
+    }
+
+    #[test]
+    fn long_poll_intervals_are_integrated() {
+        let mut health = StorageHealth::new(Duration::from_secs(15 * 60));
+        let start = local(2, 12, 0);
+        health.record(battery(0), at(start), -3600.0);
+        assert_eq!(health.record(battery(0), at(start + 15 * 60), -3600.0).charged_energy, 900.0);
+        // A failed poll in between is integrated as well, three missing polls are not
+        assert_eq!(health.record(battery(0), at(start + 45 * 60), -3600.0).charged_energy, 2700.0);
+        assert_eq!(health.record(battery(0), at(start + 105 * 60), -3600.0).charged_energy, 2700.0);
+    }
+
+    #[test]
+    fn duplicate_and_out_of_order_samples_are_ignored() {
+        let mut health = StorageHealth::new(Duration::from_secs(5));
+        let start = local(2, 12, 0);
+        health.record(battery(0), at(start), -3600.0);
+        health.record(battery(0), at(start + 60), -3600.0);
+        // Not yet updated data with the same timestamp, but a different power
+        assert_eq!(health.record(battery(0), at(start + 60), 100000.0).charged_energy, 60.0);
+        assert_eq!(health.record(battery(0), at(start + 30), 100000.0).charged_energy, 60.0);
+        // The ignored samples don't replace the last sample
+        let throughput = health.record(battery(0), at(start + 120), -3600.0);
+        assert_eq!(throughput.charged_energy, 120.0);
+        assert_eq!(throughput.discharged_energy, 0.0);
+    }
+
+    #[test]
+    fn day_counters_are_reset_at_local_midnight() {
+        let mut health = StorageHealth::new(Duration::from_secs(5));
+        let midnight = local(3, 0, 0);
+        health.record(battery(0), at(midnight - 120), -3600.0);
+        let throughput = health.record(battery(0), at(midnight - 60), -3600.0);
+        assert_eq!(throughput.day_charged_energy, 60.0);
+
+        // The minute across midnight counts for the new day
+        let throughput = health.record(battery(0), at(midnight + 60), -3600.0);
+        assert_eq!(throughput.charged_energy, 180.0);
+        assert_eq!(throughput.day_charged_energy, 120.0);
+
+        // A late sample of the previous day doesn't reset the new day again
+        assert_eq!(health.record(battery(0), at(midnight - 30), -3600.0).day_charged_energy, 120.0);
+    }
+
+    #[test]
+    fn batteries_are_counted_separately() {
+        let mut health = StorageHealth::new(Duration::from_secs(5));
+        let start = local(2, 12, 0);
+        health.record(battery(0), at(start), -3600.0);
+        health.record(battery(1), at(start), 3600.0);
+        assert_eq!(health.record(battery(0), at(start + 60), -3600.0).charged_energy, 60.0);
+        let throughput = health.record(battery(1), at(start + 60), 3600.0);
+        assert_eq!(throughput.charged_energy, 0.0);
+        assert_eq!(throughput.discharged_energy, 60.0);
+    }
+
+    #[test]
+    fn seeded_counters_are_continued() {
+        let mut health = StorageHealth::new(Duration::from_secs(5));
+        let seeded = Throughput {
+            charged_energy: 5000.0,
+            discharged_energy: 4000.0,
+            day_charged_energy: 500.0,
+            day_discharged_energy: 400.0,
+        };
+        health.seed(battery(0), at(local(2, 11, 0)), seeded);
+        health.seed(battery(1), at(local(1, 23, 0)), seeded);
+
+        let start = local(2, 12, 0);
+        assert_eq!(health.record(battery(0), at(start), -3600.0), seeded);
+        let throughput = health.record(battery(0), at(start + 60), -3600.0);
+        assert_eq!(throughput.charged_energy, 5060.0);
+        assert_eq!(throughput.day_charged_energy, 560.0);
+
+        // Seeded on the previous day, only the day counters start over
+        let throughput = health.record(battery(1), at(start), -3600.0);
+        assert_eq!(throughput.charged_energy, 5000.0);
+        assert_eq!(throughput.day_charged_energy, 0.0);
+        assert_eq!(throughput.day_discharged_energy, 0.0);
+    }
+}
@@ -0,0 +1,462 @@
+use std::{path::PathBuf, time::Duration};
+
+use fronius_api::{
+    fronius::{
+        ArchiveChannel, ArchiveScope, AsyncFronius, Capability, CommonInverterData, CumulationInverterData,
+        DeviceId, DeviceType, Error, ErrorKind, Fronius, FroniusBuilder, LastErrorStringControlData, MinMaxInverterData,
+        MinMaxSensorData, NowSensorData, NowStringControlData, OhmPilotCodeOfState,
+        RetryPolicy, SensorChannel, StatusCode, Support, ThreePhaseInverterData, TimePeriod,
+    },
+    mock::{Fault, MockServer},
+};
+use time::macros::datetime;
+
+fn fixture_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn connect() -> (MockServer, Fronius) {
+    let server = MockServer::start(fixture_dir()).expect("Mock server should start");
+    let fronius = Fronius::connect_url(server.url()).expect("Connecting to the mock should succeed");
+    (server, fronius)
+}
+
+fn device(id: u8) -> DeviceId {
+    DeviceId::try_from(id).unwrap()
+}
+
+#[test]
+fn connect_rejects_unsupported_api_version() {
+    let server = MockServer::start(fixture_dir()).unwrap();
+    server.set_fixture(
+        "GetAPIVersion",
+        serde_json::json!({"APIVersion": 0, "BaseURL": "/solar_api/", "CompatibilityRange": "0"}),
+    );
+
+    let result = Fronius::connect_url(server.url());
+    assert!(matches!(result, Err(Error::UnsupportedApiVersion(0))));
+}
+
+#[test]
+fn builder_parses_device_addresses() {
+    for (address, url) in [
+        ("10.0.0.1", "http://10.0.0.1/"),
+        ("fronius.local", "http://fronius.local/"),
+        ("fronius.local:8080", "http://fronius.local:8080/"),
+        ("fd00::10", "http://[fd00::10]/"),
+        ("[fd00::10]:8080", "http://[fd00::10]:8080/"),
+        ("https://proxy.example.com/fronius", "https://proxy.example.com/fronius/"),
+    ] {
+        let builder = FroniusBuilder::new(address).unwrap();
+        assert_eq!(builder.url().as_str(), url, "{address}");
+    }
+}
+
+#[test]
+fn builder_rejects_invalid_addresses() {
+    for address in ["", "ftp://fronius.local/", "fronius.local:port"] {
+        assert!(matches!(FroniusBuilder::new(address), Err(Error::InvalidAddress(_))), "{address:?}");
+    }
+}
+
+#[test]
+fn connect_by_host_and_port() {
+    let server = MockServer::start(fixture_dir()).unwrap();
+
+    let fronius = Fronius::builder(&format!("localhost:{}", server.addr().port()))
+        .unwrap()
+        .build()
+        .unwrap();
+    assert!(fronius.get_inverter_realtime_data_system().is_ok());
+}
+
+#[test]
+fn connect_by_url_with_path() {
+    let server = MockServer::start(fixture_dir()).unwrap();
+
+    let fronius = Fronius::builder(&format!("{}fronius/", server.url())).unwrap().build().unwrap();
+    assert!(fronius.get_logger_info().is_ok());
+}
+
+#[test]
+fn inverter_realtime_data_device() {
+    let (_server, fronius) = connect();
+
+    let common: CommonInverterData = fronius.get_inverter_realtime_data_device(&device(1)).unwrap();
+    assert_eq!(common.pac.value, Some(2298.0));
+    assert_eq!(common.udc_2.value, Some(295.7));
+    assert_eq!(common.idc_3.value, None);
+    assert_eq!(common.mppt_values()[1], (2, Some(4.1), Some(295.7)));
+    assert_eq!(common.mppt_values()[3], (4, None, None));
+
+    let three_phase: ThreePhaseInverterData =
+        fronius.get_inverter_realtime_data_device(&device(1)).unwrap();
+    assert_eq!(three_phase.uac_l2.value, Some(231.0));
+    assert_eq!(three_phase.t_ambient.unwrap().value, Some(38.0));
+
+    let cumulation: CumulationInverterData =
+        fronius.get_inverter_realtime_data_device(&device(1)).unwrap();
+    assert_eq!(cumulation.total_energy.value, Some(34567890.0));
+
+    let min_max: MinMaxInverterData = fronius.get_inverter_realtime_data_device(&device(1)).unwrap();
+    assert_eq!(min_max.day_pmax.value, Some(5120.0));
+    assert_eq!(min_max.total_uacmin.value, Some(212.7));
+}
+
+#[test]
+fn inverter_realtime_data_system() {
+    let (_server, fronius) = connect();
+
+    let system = fronius.get_inverter_realtime_data_system().unwrap();
+    assert_eq!(system.pac.values["1"], Some(2298.0));
+}
+
+#[test]
+fn inverter_info() {
+    let (_server, fronius) = connect();
+
+    let infos = fronius.get_inverter_info().unwrap();
+    let info = infos["1"].as_ref().unwrap();
+    assert_eq!(info.custom_name, "Symo");
+    assert_eq!(info.unique_id, "33223210");
+}
+
+#[test]
+fn active_device_info() {
+    let (_server, fronius) = connect();
+
+    let devices = fronius.get_active_device_info().unwrap();
+    let inverter = devices[&DeviceType::Inverter]["1"].as_ref().unwrap();
+    assert_eq!(inverter.serial, "33223210");
+    assert!(devices[&DeviceType::Meter].contains_key("0"));
+}
+
+#[test]
+fn logger_info() {
+    let (_server, fronius) = connect();
+
+    let info = fronius.get_logger_info().unwrap();
+    assert_eq!(info.sw_version.as_deref(), Some("3.26.1-3"));
+    assert_eq!(info.utc_offset, Some(7200));
+
+    let leds = fronius.get_logger_led_info().unwrap();
+    assert_eq!(leds.power_led.unwrap().color, "green");
+}
+
+#[test]
+fn meter_realtime_data() {
+    let (_server, fronius) = connect();
+
+    let meter = fronius.get_meter_realtime_data_device(&device(0)).unwrap();
+    assert_eq!(meter.power_real_p_sum, 923.0);
+    assert_eq!(meter.energy_real_wac_sum_consumed, 1234000.0);
+
+    let system = fronius.get_meter_realtime_data_system().unwrap();
+    assert_eq!(system["0"].power_real_p_sum, 923.0);
+}
+
+#[test]
+fn storage_realtime_data() {
+    let (_server, fronius) = connect();
+
+    let storage = fronius.get_storage_realtime_data_device(&device(0)).unwrap();
+    assert_eq!(storage.controller.state_of_charge_relative, 76.5);
+    assert_eq!(storage.controller.designed_capacity, Some(11040.0));
+    assert_eq!(storage.modules[0].cycle_count_battery_cell, Some(312.0));
+    assert_eq!(storage.modules[0].details.as_ref().unwrap().serial, "BYD-M-0001");
+
+    let system = fronius.get_storage_realtime_data_system().unwrap();
+    assert_eq!(system["0"].controller.capacity_maximum, 10240.0);
+}
+
+#[test]
+fn ohm_pilot_realtime_data() {
+    let (_server, fronius) = connect();
+
+    let ohm_pilot = fronius.get_ohm_pilot_realtime_data_device(&device(0)).unwrap();
+    assert_eq!(ohm_pilot.code_of_state, OhmPilotCodeOfState::UpAndRunning);
+    assert_eq!(ohm_pilot.power_real_pac_sum, 1500.0);
+
+    let system = fronius.get_ohm_pilot_realtime_data_system().unwrap();
+    assert_eq!(system["0"].temperature_channel_1, 55.3);
+}
+
+#[test]
+fn sensor_realtime_data() {
+    let (_server, fronius) = connect();
+
+    let now: NowSensorData = fronius.get_sensor_realtime_data(&device(0)).unwrap();
+    assert_eq!(now.value(SensorChannel::Irradiance), Some(812.0));
+    assert_eq!(now.value(SensorChannel::CurrentLoop), None);
+
+    let min_max: MinMaxSensorData = fronius.get_sensor_realtime_data(&device(0)).unwrap();
+    let module = min_max.channel(SensorChannel::ModuleTemperature).unwrap();
+    assert_eq!(module.day.as_ref().unwrap().max, Some(48.5));
+}
+
+#[test]
+fn string_realtime_data() {
+    let (_server, fronius) = connect();
+
+    let now: NowStringControlData = fronius.get_string_realtime_data(&device(0)).unwrap();
+    assert_eq!(now.values(), vec![(1, Some(4.21)), (2, Some(4.19)), (3, Some(1.02))]);
+
+    let last_error: LastErrorStringControlData = fronius.get_string_realtime_data(&device(0)).unwrap();
+    assert!(last_error.strings.contains_key("3"));
+
+    let sum = fronius
+        .get_string_current_sum_data(&device(0), TimePeriod::Day)
+        .unwrap();
+    assert_eq!(sum.values()[2], (3, Some(5.4)));
+}
+
+#[test]
+fn power_flow_realtime_data() {
+    let (_server, fronius) = connect();
+
+    let power_flow = fronius.get_power_flow_realtime_data().unwrap();
+    assert_eq!(power_flow.site.p_pv, 3988.0);
+    assert_eq!(power_flow.inverters["1"].soc, Some(76.5));
+}
+
+#[test]
+fn archive_data() {
+    let (_server, fronius) = connect();
+
+    let archive = fronius
+        .get_archive_data(
+            datetime!(2024-05-01 00:00 +2),
+            datetime!(2024-05-01 23:59:59 +2),
+            &[ArchiveChannel::PowerRealPacSum],
+            &ArchiveScope::Device(DeviceType::Inverter, device(1)),
+        )
+        .unwrap();
+    let series = archive["inverter/1"]
+        .channel(ArchiveChannel::PowerRealPacSum)
+        .unwrap();
+    assert_eq!(series.values[1], (datetime!(2024-05-01 00:05 +2), 150.0));
+}
+
+#[test]
+fn archive_data_rejects_reversed_range() {
+    let (_server, fronius) = connect();
+
+    let result = fronius.get_archive_data(
+        datetime!(2024-05-02 00:00 UTC),
+        datetime!(2024-05-01 00:00 UTC),
+        &[ArchiveChannel::PowerRealPacSum],
+        &ArchiveScope::System,
+    );
+    assert!(matches!(result, Err(Error::InvalidArchiveRange(_, _))));
+}
+
+#[test]
+fn error_status_is_returned_as_response_error() {
+    let (server, fronius) = connect();
+    server.inject_fault("GetMeterRealtimeData", Fault::Status(StatusCode::DeviceNotAvailable));
+
+    let result = fronius.get_meter_realtime_data_device(&device(0));
+    assert!(matches!(result, Err(Error::Response(_))));
+
+    server.clear_faults();
+    assert!(fronius.get_meter_realtime_data_device(&device(0)).is_ok());
+}
+
+#[test]
+fn error_responses_are_classified() {
+    let server = MockServer::start(fixture_dir()).unwrap();
+    let fronius = FroniusBuilder::from_url(server.url())
+        .retry_policy(RetryPolicy::none())
+        .build()
+        .unwrap();
+
+    for (code, kind) in [
+        (StatusCode::NotImplemented, ErrorKind::Unsupported),
+        (StatusCode::NotSupported, ErrorKind::Unsupported),
+        (StatusCode::Timeout, ErrorKind::Transient),
+        (StatusCode::LNRequestTimeout, ErrorKind::Transient),
+        (StatusCode::DeviceNotAvailable, ErrorKind::Transient),
+        (StatusCode::ArgumentError, ErrorKind::Permanent),
+        (StatusCode::UnknownError, ErrorKind::Permanent),
+    ] {
+        server.inject_fault("GetLoggerInfo", Fault::Status(code));
+        let error = fronius.get_logger_info().unwrap_err();
+        assert_eq!(error.kind(), kind, "{code:?}");
+        let status = error.status().expect("Error response should carry a status");
+        assert_eq!(status.code(), code);
+        assert_eq!(status.reason(), "injected fault");
+        assert_eq!(status.user_message(), "");
+    }
+}
+
+#[test]
+fn missing_fixture_is_not_implemented() {
+    let (_server, fronius) = connect();
+
+    let result: Result<serde_json::Value, _> =
+        fronius.make_request("GetUnknownData.cgi", [] as [(&str, &str); 0]);
+    assert!(matches!(result, Err(Error::Response(_))));
+}
+
+#[test]
+fn malformed_body_fails_to_decode() {
+    let (server, fronius) = connect();
+    server.inject_fault("GetPowerFlowRealtimeData", Fault::Malformed);
+
+    assert!(fronius.get_power_flow_realtime_data().is_err());
+}
+
+#[test]
+fn delayed_response_is_awaited() {
+    let (server, fronius) = connect();
+    server.inject_fault("GetLoggerInfo", Fault::Delay(Duration::from_millis(200)));
+
+    let start = std::time::Instant::now();
+    assert!(fronius.get_logger_info().is_ok());
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+#[test]
+fn probe_capabilities() {
+    let (server, fronius) = connect();
+    server.inject_fault("GetStorageRealtimeData", Fault::Status(StatusCode::NotSupported));
+    server.inject_fault("GetOhmPilotRealtimeData", Fault::Status(StatusCode::NotImplemented));
+
+    let report = fronius.probe_capabilities();
+    assert!(report.is_supported(Capability::CommonInverterData));
+    assert!(report.is_supported(Capability::MeterRealtimeData));
+    assert!(report.is_supported(Capability::PowerFlowRealtimeData));
+    assert_eq!(report.support(Capability::StorageRealtimeData), Support::Unsupported);
+    assert_eq!(report.support(Capability::OhmPilotRealtimeData), Support::Unsupported);
+    assert_eq!(report.iter().count(), Capability::ALL.len());
+}
+
+#[test]
+fn probe_capabilities_without_devices() {
+    let (server, fronius) = connect();
+    server.set_fixture(
+        "GetActiveDeviceInfo",
+        serde_json::json!({"Data": {"Inverter": {"1": {"DT": 1, "Serial": "33223210"}}}}),
+    );
+
+    let report = fronius.probe_capabilities();
+    assert!(report.is_supported(Capability::CommonInverterData));
+    assert_eq!(report.support(Capability::MeterRealtimeData), Support::NoDevice);
+    assert_eq!(report.support(Capability::NowSensorData), Support::NoDevice);
+}
+
+fn quick_retries(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(10),
+    }
+}
+
+#[test]
+fn retryable_status_is_retried() {
+    let server = MockServer::start(fixture_dir()).unwrap();
+    let fronius = FroniusBuilder::from_url(server.url())
+        .retry_policy(quick_retries(3))
+        .build()
+        .unwrap();
+    server.inject_fault("GetMeterRealtimeData", Fault::Status(StatusCode::LNRequestTimeout));
+
+    let result = fronius.get_meter_realtime_data_device(&device(0));
+    assert!(matches!(result, Err(Error::Response(_))));
+    assert_eq!(server.request_count("GetMeterRealtimeData"), 4);
+}
+
+#[test]
+fn permanent_status_is_not_retried() {
+    let server = MockServer::start(fixture_dir()).unwrap();
+    let fronius = FroniusBuilder::from_url(server.url())
+        .retry_policy(quick_retries(3))
+        .build()
+        .unwrap();
+    server.inject_fault("GetMeterRealtimeData", Fault::Status(StatusCode::NotImplemented));
+
+    assert!(fronius.get_meter_realtime_data_device(&device(0)).is_err());
+    assert_eq!(server.request_count("GetMeterRealtimeData"), 1);
+}
+
+#[test]
+fn slow_response_times_out() {
+    let server = MockServer::start(fixture_dir()).unwrap();
+    let fronius = FroniusBuilder::from_url(server.url())
+        .timeout(Duration::from_millis(100))
+        .retry_policy(quick_retries(1))
+        .build()
+        .unwrap();
+    server.inject_fault("GetLoggerInfo", Fault::Delay(Duration::from_millis(500)));
+
+    let result = fronius.get_logger_info();
+    assert!(matches!(result, Err(Error::Request(error)) if error.is_timeout()));
+    assert_eq!(server.request_count("GetLoggerInfo"), 2);
+}
+
+#[test]
+fn backoff_grows_exponentially_up_to_the_limit() {
+    let policy = RetryPolicy {
+        max_retries: 10,
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_secs(1),
+    };
+    for (retry, full) in [(0, 100), (1, 200), (2, 400), (3, 800), (4, 1000), (9, 1000)] {
+        let backoff = policy.backoff(retry);
+        let full = Duration::from_millis(full);
+        assert!(backoff >= full / 2 && backoff <= full, "{retry}: {backoff:?}");
+    }
+}
+
+#[tokio::test]
+async fn async_client() {
+    let server = MockServer::start(fixture_dir()).unwrap();
+    let fronius = AsyncFronius::connect_url(server.url()).await.unwrap();
+
+    let common: CommonInverterData = fronius
+        .get_inverter_realtime_data_device(&device(1))
+        .await
+        .unwrap();
+    assert_eq!(common.pac.value, Some(2298.0));
+
+    let device_id = device(0);
+    let (meter, storage, power_flow) = tokio::join!(
+        fronius.get_meter_realtime_data_device(&device_id),
+        fronius.get_storage_realtime_data_device(&device_id),
+        fronius.get_power_flow_realtime_data(),
+    );
+    assert_eq!(meter.unwrap().power_real_p_sum, 923.0);
+    assert_eq!(storage.unwrap().controller.voltage_dc, 402.1);
+    assert_eq!(power_flow.unwrap().site.p_grid, Some(-1375.0));
+
+    server.inject_fault("GetInverterInfo", Fault::Status(StatusCode::Timeout));
+    assert!(matches!(fronius.get_inverter_info().await, Err(Error::Response(_))));
+}
+
+#[test]
+fn timestamped_returns_response_timestamp() {
+    let (_server, fronius) = connect();
+
+    let before = time::OffsetDateTime::now_utc().replace_nanosecond(0).unwrap();
+    let logger = fronius.timestamped(Fronius::get_logger_info).unwrap();
+    let timestamp = logger.timestamp.expect("Response should carry a timestamp");
+    assert!(timestamp >= before && timestamp <= time::OffsetDateTime::now_utc());
+    assert!(logger.data.unique_id.is_some());
+
+    let nothing = fronius.timestamped(|_| Ok(())).unwrap();
+    assert!(nothing.timestamp.is_none());
+}
+
+#[tokio::test]
+async fn async_timestamped_returns_response_timestamp() {
+    let server = MockServer::start(fixture_dir()).unwrap();
+    let fronius = AsyncFronius::connect_url(server.url()).await.unwrap();
+
+    let power_flow = fronius
+        .timestamped(|fronius| async move { fronius.get_power_flow_realtime_data().await })
+        .await
+        .unwrap();
+    assert!(power_flow.timestamp.is_some());
+    assert_eq!(power_flow.data.site.p_grid, Some(-1375.0));
+}